@@ -0,0 +1,39 @@
+// REF: https://en.wikipedia.org/wiki/ANSI_escape_code#SGR_(Select_Graphic_Rendition)_parameters
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Default,
+    Indexed(u8),
+    Rgb(u8, u8, u8)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Attributes {
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub faint: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub blink: bool,
+    pub inverse: bool,
+    pub hidden: bool,
+    pub strikethrough: bool
+}
+
+impl Default for Attributes {
+    fn default() -> Self {
+        Self {
+            fg: Color::Default,
+            bg: Color::Default,
+            bold: false,
+            faint: false,
+            italic: false,
+            underline: false,
+            blink: false,
+            inverse: false,
+            hidden: false,
+            strikethrough: false
+        }
+    }
+}
@@ -1,16 +1,27 @@
 use winit::event::{ModifiersState, VirtualKeyCode::{self, *}};
 
+pub const ENQ_CHAR: char = 5 as char;
 pub const NEWLINE_CHAR: char = 10 as char;
-pub const SPACE_CHAR: char = 32 as char;
+pub const VT_CHAR: char = 11 as char;
+pub const FF_CHAR: char = 12 as char;
 pub const TAB_CHAR: char = 9 as char;
 pub const BACK_CHAR: char = 8 as char;
-pub const ESC_CHAR: char = 27 as char;
 pub const CR_CHAR: char = 13 as char;
-pub const BELL_CHAR: char = 7 as char;
+pub const SO_CHAR: char = 14 as char;
+pub const SI_CHAR: char = 15 as char;
 
 pub struct InputChar {}
 impl InputChar {
     pub fn from(key: VirtualKeyCode, modifiers: ModifiersState) -> Option<char> {
+        let c = Self::from_key(key, modifiers)?;
+        if modifiers.ctrl() {
+            Some(control_char(c))
+        } else {
+            Some(c)
+        }
+    }
+
+    fn from_key(key: VirtualKeyCode, modifiers: ModifiersState) -> Option<char> {
         match key {
             Key0 if modifiers.shift() => Some(')'),
             Key1 if modifiers.shift() => Some('!'),
@@ -61,33 +72,6 @@ impl InputChar {
             Y if modifiers.shift() => Some('Y'),
             Z if modifiers.shift() => Some('Z'),
 
-            A if modifiers.ctrl() => Some(0x01 as char),
-            B if modifiers.ctrl() => Some(0x02 as char),
-            C if modifiers.ctrl() => Some(0x03 as char),
-            D if modifiers.ctrl() => Some(0x04 as char),
-            E if modifiers.ctrl() => Some(0x05 as char),
-            F if modifiers.ctrl() => Some(0x06 as char),
-            G if modifiers.ctrl() => Some(0x07 as char),
-            H if modifiers.ctrl() => Some(0x08 as char),
-            I if modifiers.ctrl() => Some(0x09 as char),
-            J if modifiers.ctrl() => Some(0x10 as char),
-            K if modifiers.ctrl() => Some(0x11 as char),
-            L if modifiers.ctrl() => Some(0x12 as char),
-            M if modifiers.ctrl() => Some(0x13 as char),
-            N if modifiers.ctrl() => Some(0x14 as char),
-            O if modifiers.ctrl() => Some(0x15 as char),
-            P if modifiers.ctrl() => Some(0x16 as char),
-            Q if modifiers.ctrl() => Some(0x17 as char),
-            R if modifiers.ctrl() => Some(0x18 as char),
-            S if modifiers.ctrl() => Some(0x19 as char),
-            T if modifiers.ctrl() => Some(0x1a as char),
-            U if modifiers.ctrl() => Some(0x21 as char),
-            V if modifiers.ctrl() => Some(0x22 as char),
-            W if modifiers.ctrl() => Some(0x23 as char),
-            X if modifiers.ctrl() => Some(0x24 as char),
-            Y if modifiers.ctrl() => Some(0x25 as char),
-            Z if modifiers.ctrl() => Some(0x26 as char),
-
             A => Some('a'),
             B => Some('b'),
             C => Some('c'),
@@ -145,49 +129,88 @@ impl InputChar {
             RBracket if modifiers.shift() => Some('}'),
             RBracket => Some(']'),
 
-            Return => Some('\n'),
+            NumpadAdd => Some('+'),
+            NumpadSubtract => Some('-'),
+            NumpadMultiply => Some('*'),
+            NumpadDivide => Some('/'),
+            NumpadDecimal => Some('.'),
+            NumpadComma => Some(','),
+            NumpadEquals => Some('='),
+
+            Return | NumpadEnter => Some(CR_CHAR),
 
             _ => None
         }
     }
 }
 
-pub struct EscapeCode {}
-impl EscapeCode {
-    fn parse_param(input: &[u8]) -> (&[u8], Vec<u8>) {
-        if input.len() > 0 {
-            let found: Vec<u8> = input.iter().by_ref().take_while(|&c| *c >= 0x30 && *c <= 0x3F).cloned().collect();
-            let remain = &input[found.len()..];
-            return (remain, found);
-        }
-        (input, vec![])
+// Ctrl clears the top bits of the character's ASCII value, so Ctrl+A is 0x01
+// and Ctrl+[ is ESC. Ctrl+Space is NUL and Ctrl+? is DEL.
+fn control_char(c: char) -> char {
+    match c {
+        ' ' => '\0',
+        '?' => 0x7f as char,
+        '@'..='~' => (c as u8 & 0x1f) as char,
+        _ => c
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn parse_intermediates(input: &[u8]) -> (&[u8], Vec<u8>) {
-        if input.len() > 0 {
-            let found: Vec<u8> = input.iter().by_ref().take_while(|&c| *c >= 0x20 && *c <= 0x2F).cloned().collect();
-            let remain = &input[found.len()..];
-            return (remain, found);
+    fn ctrl(key: VirtualKeyCode, shift: bool) -> Option<char> {
+        let mut modifiers = ModifiersState::CTRL;
+        if shift {
+            modifiers |= ModifiersState::SHIFT;
         }
-        (input, vec![])
+        InputChar::from(key, modifiers)
     }
 
-    fn parse_final(input: &[u8]) -> (&[u8], u8) {
-        if input.len() > 0 {
-            let found: Vec<u8> = input.iter().by_ref().take_while(|&c| *c >= 0x40 && *c <= 0x7E).cloned().collect();
-            let remain = &input[1..];
-            return (remain, found[0]);
+    #[test]
+    fn ctrl_letters() {
+        let letters = [A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z];
+        for (i, key) in letters.iter().enumerate() {
+            assert_eq!(ctrl(*key, false), Some((i as u8 + 1) as char), "Ctrl+{:?}", key);
+            assert_eq!(ctrl(*key, true), Some((i as u8 + 1) as char), "Ctrl+Shift+{:?}", key);
         }
-        (input, 0)
     }
 
-    pub fn parse_csi(buf: &[u8]) -> (&[u8], Vec<u8>, Vec<u8>, u8) {
-        if buf.len() > 0 && buf[0] == '[' as u8 {
-            let (remain, param) = Self::parse_param(&buf[1..]);
-            let (remain, intermediates) = Self::parse_intermediates(remain);
-            let (remain, final_byte) = Self::parse_final(remain);
-            return (remain, param, intermediates, final_byte);
+    #[test]
+    fn ctrl_symbols() {
+        assert_eq!(ctrl(Space, false), Some('\0'));
+        assert_eq!(ctrl(Key2, true), Some('\0'));
+        assert_eq!(ctrl(LBracket, false), Some(0x1b as char));
+        assert_eq!(ctrl(Backslash, false), Some(0x1c as char));
+        assert_eq!(ctrl(RBracket, false), Some(0x1d as char));
+        assert_eq!(ctrl(Key6, true), Some(0x1e as char));
+        assert_eq!(ctrl(Minus, true), Some(0x1f as char));
+        assert_eq!(ctrl(Slash, true), Some(0x7f as char));
+    }
+
+    #[test]
+    fn whole_c0_range() {
+        let keys = [
+            (Space, false), (A, false), (B, false), (C, false), (D, false), (E, false), (F, false), (G, false),
+            (H, false), (I, false), (J, false), (K, false), (L, false), (M, false), (N, false), (O, false),
+            (P, false), (Q, false), (R, false), (S, false), (T, false), (U, false), (V, false), (W, false),
+            (X, false), (Y, false), (Z, false), (LBracket, false), (Backslash, false), (RBracket, false),
+            (Key6, true), (Minus, true)
+        ];
+        for (code, (key, shift)) in keys.iter().enumerate() {
+            assert_eq!(ctrl(*key, *shift), Some(code as u8 as char), "C0 code {:#04x}", code);
         }
-        (buf, vec![], vec![], 0)
+    }
+
+    #[test]
+    fn ctrl_leaves_other_keys() {
+        assert_eq!(ctrl(Key1, false), Some('1'));
+        assert_eq!(ctrl(Tab, false), Some(TAB_CHAR));
+        assert_eq!(ctrl(Return, false), Some(CR_CHAR));
+        // Enter is Ctrl+M, not Ctrl+J
+        assert_eq!(InputChar::from(Return, ModifiersState::empty()), ctrl(M, false));
+        assert_ne!(InputChar::from(Return, ModifiersState::empty()), ctrl(J, false));
+        assert_eq!(InputChar::from(A, ModifiersState::empty()), Some('a'));
+        assert_eq!(InputChar::from(A, ModifiersState::SHIFT), Some('A'));
     }
 }
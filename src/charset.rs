@@ -0,0 +1,98 @@
+// REF: https://vt100.net/docs/vt220-rm/chapter4.html
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Charset {
+    Ascii,
+    DecSpecialGraphics,
+    Uk
+}
+
+impl Charset {
+    // The final byte of a designation sequence like `ESC ( 0`
+    pub fn from_designator(byte: u8) -> Option<Charset> {
+        match byte {
+            b'B' => Some(Charset::Ascii),
+            b'0' => Some(Charset::DecSpecialGraphics),
+            b'A' => Some(Charset::Uk),
+            _ => None
+        }
+    }
+
+    pub fn map(self, c: char) -> char {
+        match self {
+            Charset::Ascii => c,
+            Charset::Uk => if c == '#' { '£' } else { c },
+            Charset::DecSpecialGraphics => match c {
+                '_' => ' ',
+                '`' => '◆',
+                'a' => '▒',
+                'b' => '␉',
+                'c' => '␌',
+                'd' => '␍',
+                'e' => '␊',
+                'f' => '°',
+                'g' => '±',
+                'h' => '␤',
+                'i' => '␋',
+                'j' => '┘',
+                'k' => '┐',
+                'l' => '┌',
+                'm' => '└',
+                'n' => '┼',
+                'o' => '⎺',
+                'p' => '⎻',
+                'q' => '─',
+                'r' => '⎼',
+                's' => '⎽',
+                't' => '├',
+                'u' => '┤',
+                'v' => '┴',
+                'w' => '┬',
+                'x' => '│',
+                'y' => '≤',
+                'z' => '≥',
+                '{' => 'π',
+                '|' => '≠',
+                '}' => '£',
+                '~' => '·',
+                _ => c
+            }
+        }
+    }
+}
+
+// The G0-G3 character sets, with the one invoked into GL used for printing.
+// A single shift only applies to the next printed character.
+#[derive(Debug, Clone, Copy)]
+pub struct Charsets {
+    sets: [Charset; 4],
+    active: usize,
+    single_shift: Option<usize>
+}
+
+impl Charsets {
+    pub fn new() -> Self {
+        Self {
+            sets: [Charset::Ascii; 4],
+            active: 0,
+            single_shift: None
+        }
+    }
+
+    pub fn designate(&mut self, slot: usize, charset: Charset) {
+        self.sets[slot] = charset;
+    }
+
+    pub fn locking_shift(&mut self, slot: usize) {
+        self.active = slot;
+    }
+
+    pub fn single_shift(&mut self, slot: usize) {
+        self.single_shift = Some(slot);
+    }
+
+    pub fn translate(&mut self, c: char) -> char {
+        let slot = self.single_shift.take().unwrap_or(self.active);
+        self.sets[slot].map(c)
+    }
+}
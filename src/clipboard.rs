@@ -0,0 +1,137 @@
+use std::{io::Write, process::{Command, Stdio}};
+
+// Whether programs running in the terminal can access the clipboard with OSC 52.
+// Reading is a security concern since any remote host could grab its content.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClipboardPolicy {
+    Deny,
+    AllowWrite,
+    AllowReadWrite
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Selection {
+    Clipboard,
+    Primary
+}
+
+pub struct Clipboard {
+    pub policy: ClipboardPolicy,
+    // macOS has no primary selection, it's kept here instead
+    primary: String
+}
+
+impl Clipboard {
+    pub fn new(policy: ClipboardPolicy) -> Self {
+        Self { policy, primary: String::new() }
+    }
+
+    pub fn store(&mut self, selection: Selection, text: &str) {
+        if cfg!(target_os = "macos") && selection == Selection::Primary {
+            self.primary = text.to_string();
+            return;
+        }
+        let (program, args) = Self::copy_command(selection);
+        let child = Command::new(program).args(args).stdin(Stdio::piped()).spawn();
+        match child {
+            Ok(mut child) => {
+                if let Some(stdin) = child.stdin.as_mut() {
+                    stdin.write_all(text.as_bytes()).ok();
+                }
+                child.wait().ok();
+            },
+            Err(err) => println!("Failed to write to the clipboard: {}", err)
+        }
+    }
+
+    pub fn load(&self, selection: Selection) -> Option<String> {
+        if cfg!(target_os = "macos") && selection == Selection::Primary {
+            return Some(self.primary.clone());
+        }
+        let (program, args) = Self::paste_command(selection);
+        let output = Command::new(program).args(args).output().ok()?;
+        String::from_utf8(output.stdout).ok()
+    }
+
+    fn copy_command(selection: Selection) -> (&'static str, &'static [&'static str]) {
+        if cfg!(target_os = "macos") {
+            ("pbcopy", &[])
+        } else if selection == Selection::Primary {
+            ("xclip", &["-selection", "primary"])
+        } else {
+            ("xclip", &["-selection", "clipboard"])
+        }
+    }
+
+    fn paste_command(selection: Selection) -> (&'static str, &'static [&'static str]) {
+        if cfg!(target_os = "macos") {
+            ("pbpaste", &[])
+        } else if selection == Selection::Primary {
+            ("xclip", &["-selection", "primary", "-o"])
+        } else {
+            ("xclip", &["-selection", "clipboard", "-o"])
+        }
+    }
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut output = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let group = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(BASE64_CHARS[(group >> (18 - i * 6) & 0x3F) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+pub fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let mut output = vec![];
+    let mut group = 0u32;
+    let mut bits = 0;
+    // Line wrapped payloads are accepted
+    for &c in input.iter().filter(|c| !c.is_ascii_whitespace()).take_while(|&&c| c != b'=') {
+        let value = BASE64_CHARS.iter().position(|&b| b == c)? as u32;
+        group = group << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((group >> bits) as u8);
+            group &= (1 << bits) - 1;
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_round_trip() {
+        for text in ["", "a", "ab", "abc", "abcd", "héllo wörld ✓ 中文"] {
+            let encoded = encode_base64(text.as_bytes());
+            assert_eq!(encoded.len() % 4, 0);
+            assert_eq!(decode_base64(encoded.as_bytes()), Some(text.as_bytes().to_vec()), "{:?}", text);
+        }
+        assert_eq!(encode_base64(b"hello"), "aGVsbG8=");
+    }
+
+    #[test]
+    fn base64_wrapped_input() {
+        assert_eq!(decode_base64(b"aGVs\nbG8="), Some(b"hello".to_vec()));
+        assert_eq!(decode_base64(b"aGVs\r\n bG8=\n"), Some(b"hello".to_vec()));
+    }
+
+    #[test]
+    fn base64_invalid_input() {
+        assert_eq!(decode_base64(b"aGV*"), None);
+    }
+}
@@ -1,3 +1,21 @@
+use winit::event::VirtualKeyCode;
+
+use crate::clipboard::ClipboardPolicy;
+
 pub const TERMINAL_COLS: i32 = 80;
 pub const TERMINAL_ROWS: i32 = 24;
 pub const TITLEBAR_MARGIN: f32 = 30.0;
+pub const TERMINAL_NAME: &str = "snarkyterm";
+// Empty like xterm, any ENQ in the output would type it into the shell
+pub const ANSWERBACK: &str = "";
+pub const CLIPBOARD_POLICY: ClipboardPolicy = ClipboardPolicy::AllowWrite;
+pub const LINK_OPENER: &str = "xdg-open";
+// Shortcuts are Ctrl+Shift and these keys. Ctrl+Shift with the cursor keys
+// is sent to the application, editors bind it.
+pub const PASTE_KEY: VirtualKeyCode = VirtualKeyCode::V;
+pub const PREVIOUS_PROMPT_KEY: VirtualKeyCode = VirtualKeyCode::K;
+pub const NEXT_PROMPT_KEY: VirtualKeyCode = VirtualKeyCode::J;
+
+// Whether characters of ambiguous East Asian Width take two cells, like
+// they do in CJK locales
+pub const AMBIGUOUS_WIDE: bool = false;
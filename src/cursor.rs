@@ -5,12 +5,13 @@ pub enum CursorDirection {
     Down,
     Left,
     Right,
-    NextLine,
-    BOL,
-    EOL,
-    BOF
+    BOL
 }
 
+// The cursor position is relative to the top left corner of the visible screen.
+// The column can go one cell past the last column, meaning the next printed
+// character will be wrapped to the next line.
+#[derive(Clone, Copy)]
 pub struct Cursor {
     pub row: usize,
     pub col: usize
@@ -26,12 +27,12 @@ impl Cursor {
            CursorDirection::Up => {
                 if self.row > 0 {
                     self.row -= 1;
-                } else {
-                    self.move_to(CursorDirection::BOL);
                 }
             },
             CursorDirection::Down => {
-                self.row += 1;
+                if self.row < TERMINAL_ROWS as usize - 1 {
+                    self.row += 1;
+                }
             }
             CursorDirection::Left => {
                 if self.col > 0 {
@@ -42,24 +43,23 @@ impl Cursor {
             CursorDirection::Right => {
                 if self.col < TERMINAL_COLS as usize {
                     self.col += 1;
-                } else {
-                    self.move_to(CursorDirection::NextLine);
                 }
             },
-            CursorDirection::NextLine => {
-                self.move_to(CursorDirection::Down);
-                self.move_to(CursorDirection::BOL);
-            },
             CursorDirection::BOL => {
                 self.col = 0;
-            },
-            CursorDirection::EOL => {
-                self.col = TERMINAL_COLS as usize;
-            },
-            CursorDirection::BOF => {
-                self.row = 0;
-                self.col = 0;
             }
         }
     }
+
+    // Move to an absolute position, clamped to the visible screen
+    pub fn set_position(&mut self, row: usize, col: usize) {
+        self.row = row.min(TERMINAL_ROWS as usize - 1);
+        self.col = col.min(TERMINAL_COLS as usize - 1);
+    }
+
+    pub fn move_by(&mut self, rows: isize, cols: isize) {
+        let row = (self.row as isize + rows).max(0) as usize;
+        let col = (self.col.min(TERMINAL_COLS as usize - 1) as isize + cols).max(0) as usize;
+        self.set_position(row, col);
+    }
 }
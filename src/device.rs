@@ -1,4 +1,4 @@
-use std::{io::Read, thread, u16};
+use std::{fs, io::{Read, Write}, path::{Path, PathBuf}, thread, u16};
 use portable_pty::{CommandBuilder, PtyPair, PtySize, native_pty_system};
 use winit::event_loop::EventLoopProxy;
 
@@ -11,11 +11,12 @@ pub enum CustomEvent {
 }
 
 pub struct Shell {
-    pair: PtyPair
+    pair: PtyPair,
+    pid: Option<u32>
 }
 
 impl Shell {
-    pub fn new(proxy: EventLoopProxy<CustomEvent>) -> Self {
+    pub fn new(proxy: EventLoopProxy<CustomEvent>, cwd: Option<&Path>) -> Self {
         let pty_system = native_pty_system();
         let pair = pty_system.openpty(PtySize {
             rows: TERMINAL_ROWS as u16,
@@ -23,8 +24,12 @@ impl Shell {
             pixel_width: 0,
             pixel_height: 0
         }).unwrap();
-        let cmd = CommandBuilder::new("/bin/bash");
-        let _child = pair.slave.spawn_command(cmd).unwrap();
+        let mut cmd = CommandBuilder::new("/bin/bash");
+        if let Some(cwd) = cwd {
+            cmd.cwd(cwd);
+        }
+        let child = pair.slave.spawn_command(cmd).unwrap();
+        let pid = child.process_id();
 
         let mut reader = pair.master.try_clone_reader().unwrap();
         let sender = proxy.clone();
@@ -42,11 +47,17 @@ impl Shell {
         });
 
         Self {
-            pair
+            pair,
+            pid
         }
     }
 
+    // The working directory of the shell process, only available where procfs is
+    pub fn cwd(&self) -> Option<PathBuf> {
+        fs::read_link(format!("/proc/{}/cwd", self.pid?)).ok()
+    }
+
     pub fn send(&mut self, data: &[u8]) {
-        self.pair.master.write(data).ok();
+        self.pair.master.write_all(data).ok();
     }
 }
@@ -0,0 +1,87 @@
+use std::{collections::HashSet, process::Command};
+
+// REF: https://gist.github.com/egmontkob/eb114294efbcd5adb1944c9f3cb5feda
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hyperlink {
+    pub id: Option<String>,
+    pub uri: String
+}
+
+// Cells only keep an index into this table, so the URI isn't copied into
+// every cell of the linked text. Entries no cell refers to anymore are freed
+// and their index is reused.
+pub struct Hyperlinks {
+    links: Vec<Option<Hyperlink>>,
+    last: Option<u32>
+}
+
+impl Hyperlinks {
+    pub fn new() -> Self {
+        Self { links: vec![], last: None }
+    }
+
+    // Links with an id are the same link only when both the id and the URI match,
+    // links without an id are a new entry unless the last link is opened again
+    pub fn intern(&mut self, link: Hyperlink) -> u32 {
+        let existing = if link.id.is_some() {
+            self.links.iter().position(|l| l.as_ref() == Some(&link)).map(|index| index as u32)
+        } else {
+            self.last.filter(|&index| self.get(index) == Some(&link))
+        };
+        let index = match existing {
+            Some(index) => index,
+            None => match self.links.iter().position(|l| l.is_none()) {
+                Some(index) => {
+                    self.links[index] = Some(link);
+                    index as u32
+                },
+                None => {
+                    self.links.push(Some(link));
+                    self.links.len() as u32 - 1
+                }
+            }
+        };
+        self.last = Some(index);
+        index
+    }
+
+    pub fn get(&self, index: u32) -> Option<&Hyperlink> {
+        self.links.get(index as usize)?.as_ref()
+    }
+
+    // Free the entries that aren't in use
+    pub fn retain(&mut self, used: &HashSet<u32>) {
+        for (index, link) in self.links.iter_mut().enumerate() {
+            if !used.contains(&(index as u32)) {
+                *link = None;
+            }
+        }
+        if matches!(self.last, Some(index) if !used.contains(&index)) {
+            self.last = None;
+        }
+    }
+}
+
+pub trait Opener {
+    fn open(&self, uri: &str);
+}
+
+// Open the URI with an external program, like `xdg-open` or `open`
+pub struct CommandOpener {
+    program: String
+}
+
+impl CommandOpener {
+    pub fn new(program: &str) -> Self {
+        Self { program: program.to_string() }
+    }
+}
+
+impl Opener for CommandOpener {
+    fn open(&self, uri: &str) {
+        if let Err(err) = Command::new(&self.program).arg(uri).spawn() {
+            println!("Failed to open {}: {}", uri, err);
+        }
+    }
+}
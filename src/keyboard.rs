@@ -0,0 +1,213 @@
+use winit::event::{ModifiersState, VirtualKeyCode::{self, *}};
+
+use crate::{characters::InputChar, modes::{Mode, Modes}};
+
+// REF: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-PC-Style-Function-Keys
+
+// The bytes sent to the shell for a key press
+pub fn encode_key(key: VirtualKeyCode, modifiers: ModifiersState, modes: &Modes) -> Option<Vec<u8>> {
+    let modifier = modifier_param(modifiers);
+    let sequence = if let Some(action) = cursor_key(key) {
+        match modifier {
+            Some(modifier) => format!("\x1b[1;{}{}", modifier, action),
+            None if modes.get(Mode::CursorKeys) => format!("\x1bO{}", action),
+            None => format!("\x1b[{}", action)
+        }
+    } else if let Some(action) = function_key(key) {
+        match modifier {
+            Some(modifier) => format!("\x1b[1;{}{}", modifier, action),
+            None => format!("\x1bO{}", action)
+        }
+    } else if let Some(code) = tilde_key(key) {
+        match modifier {
+            Some(modifier) => format!("\x1b[{};{}~", code, modifier),
+            None => format!("\x1b[{}~", code)
+        }
+    } else if let Some(action) = keypad_key(key).filter(|_| modes.get(Mode::KeypadApplication)) {
+        format!("\x1bO{}", action)
+    } else if key == Escape {
+        String::from("\x1b")
+    } else if matches!(key, Return | NumpadEnter) && modes.get(Mode::LinefeedNewline) {
+        String::from("\r\n")
+    } else {
+        let c = InputChar::from(key, modifiers)?;
+        if modifiers.alt() && alt_sends_escape(modes) {
+            format!("\x1b{}", c)
+        } else {
+            c.to_string()
+        }
+    };
+    Some(sequence.into_bytes())
+}
+
+// Alt and Meta are the same key here, either mode makes it prefix the
+// character with ESC
+fn alt_sends_escape(modes: &Modes) -> bool {
+    modes.get(Mode::MetaSendsEscape) || modes.get(Mode::AltSendsEscape)
+}
+
+// The modifier parameter is 1 plus a bit for each modifier, it's left out
+// when no modifier is held
+fn modifier_param(modifiers: ModifiersState) -> Option<u8> {
+    let mut param = 0;
+    if modifiers.shift() {
+        param |= 1;
+    }
+    if modifiers.alt() {
+        param |= 2;
+    }
+    if modifiers.ctrl() {
+        param |= 4;
+    }
+    if param > 0 { Some(param + 1) } else { None }
+}
+
+// Keys sent as `CSI x`, or `SS3 x` in cursor keys mode
+fn cursor_key(key: VirtualKeyCode) -> Option<char> {
+    match key {
+        Up => Some('A'),
+        Down => Some('B'),
+        Right => Some('C'),
+        Left => Some('D'),
+        Home => Some('H'),
+        End => Some('F'),
+        _ => None
+    }
+}
+
+// Keys sent as `SS3 x`
+fn function_key(key: VirtualKeyCode) -> Option<char> {
+    match key {
+        F1 => Some('P'),
+        F2 => Some('Q'),
+        F3 => Some('R'),
+        F4 => Some('S'),
+        _ => None
+    }
+}
+
+// Keys sent as `CSI n ~`
+fn tilde_key(key: VirtualKeyCode) -> Option<u8> {
+    match key {
+        Insert => Some(2),
+        Delete => Some(3),
+        PageUp => Some(5),
+        PageDown => Some(6),
+        F5 => Some(15),
+        F6 => Some(17),
+        F7 => Some(18),
+        F8 => Some(19),
+        F9 => Some(20),
+        F10 => Some(21),
+        F11 => Some(23),
+        F12 => Some(24),
+        _ => None
+    }
+}
+
+// Keys sent as `SS3 x` in keypad application mode
+fn keypad_key(key: VirtualKeyCode) -> Option<char> {
+    match key {
+        Numpad0 => Some('p'),
+        Numpad1 => Some('q'),
+        Numpad2 => Some('r'),
+        Numpad3 => Some('s'),
+        Numpad4 => Some('t'),
+        Numpad5 => Some('u'),
+        Numpad6 => Some('v'),
+        Numpad7 => Some('w'),
+        Numpad8 => Some('x'),
+        Numpad9 => Some('y'),
+        NumpadMultiply => Some('j'),
+        NumpadAdd => Some('k'),
+        NumpadComma => Some('l'),
+        NumpadSubtract => Some('m'),
+        NumpadDecimal => Some('n'),
+        NumpadDivide => Some('o'),
+        NumpadEnter => Some('M'),
+        NumpadEquals => Some('X'),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(key: VirtualKeyCode, modifiers: ModifiersState, modes: &Modes) -> String {
+        String::from_utf8(encode_key(key, modifiers, modes).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn cursor_keys_mode() {
+        let mut modes = Modes::new();
+        assert_eq!(encode(Up, ModifiersState::empty(), &modes), "\x1b[A");
+        assert_eq!(encode(Home, ModifiersState::empty(), &modes), "\x1b[H");
+        modes.set(Mode::CursorKeys, true);
+        assert_eq!(encode(Up, ModifiersState::empty(), &modes), "\x1bOA");
+        assert_eq!(encode(End, ModifiersState::empty(), &modes), "\x1bOF");
+        // Modifiers always use the CSI form
+        assert_eq!(encode(Right, ModifiersState::CTRL, &modes), "\x1b[1;5C");
+    }
+
+    #[test]
+    fn modifier_parameters() {
+        let modes = Modes::new();
+        assert_eq!(encode(Right, ModifiersState::CTRL, &modes), "\x1b[1;5C");
+        assert_eq!(encode(Left, ModifiersState::SHIFT, &modes), "\x1b[1;2D");
+        assert_eq!(encode(Down, ModifiersState::ALT, &modes), "\x1b[1;3B");
+        assert_eq!(encode(Up, ModifiersState::CTRL | ModifiersState::ALT | ModifiersState::SHIFT, &modes), "\x1b[1;8A");
+    }
+
+    #[test]
+    fn tilde_keys() {
+        let modes = Modes::new();
+        assert_eq!(encode(Insert, ModifiersState::empty(), &modes), "\x1b[2~");
+        assert_eq!(encode(Delete, ModifiersState::empty(), &modes), "\x1b[3~");
+        assert_eq!(encode(PageUp, ModifiersState::empty(), &modes), "\x1b[5~");
+        assert_eq!(encode(PageDown, ModifiersState::SHIFT, &modes), "\x1b[6;2~");
+        assert_eq!(encode(F5, ModifiersState::empty(), &modes), "\x1b[15~");
+        assert_eq!(encode(F12, ModifiersState::CTRL, &modes), "\x1b[24;5~");
+    }
+
+    #[test]
+    fn function_keys() {
+        let modes = Modes::new();
+        assert_eq!(encode(F1, ModifiersState::empty(), &modes), "\x1bOP");
+        assert_eq!(encode(F4, ModifiersState::empty(), &modes), "\x1bOS");
+        assert_eq!(encode(F2, ModifiersState::SHIFT, &modes), "\x1b[1;2Q");
+        assert_eq!(encode(F3, ModifiersState::CTRL, &modes), "\x1b[1;5R");
+    }
+
+    #[test]
+    fn keypad_application_mode() {
+        let mut modes = Modes::new();
+        assert_eq!(encode(Numpad5, ModifiersState::empty(), &modes), "5");
+        assert_eq!(encode(NumpadAdd, ModifiersState::empty(), &modes), "+");
+        modes.set(Mode::KeypadApplication, true);
+        assert_eq!(encode(Numpad5, ModifiersState::empty(), &modes), "\x1bOu");
+        assert_eq!(encode(NumpadAdd, ModifiersState::empty(), &modes), "\x1bOk");
+        assert_eq!(encode(NumpadEnter, ModifiersState::empty(), &modes), "\x1bOM");
+    }
+
+    #[test]
+    fn enter_key() {
+        let mut modes = Modes::new();
+        assert_eq!(encode(Return, ModifiersState::empty(), &modes), "\r");
+        assert_eq!(encode(NumpadEnter, ModifiersState::empty(), &modes), "\r");
+        modes.set(Mode::LinefeedNewline, true);
+        assert_eq!(encode(Return, ModifiersState::empty(), &modes), "\r\n");
+        assert_eq!(encode(NumpadEnter, ModifiersState::empty(), &modes), "\r\n");
+    }
+
+    #[test]
+    fn alt_prefixes_escape() {
+        let mut modes = Modes::new();
+        assert_eq!(encode_key(X, ModifiersState::ALT, &modes), Some(b"\x1bx".to_vec()));
+        assert_eq!(encode_key(C, ModifiersState::ALT | ModifiersState::CTRL, &modes), Some(b"\x1b\x03".to_vec()));
+        modes.set(Mode::AltSendsEscape, false);
+        assert_eq!(encode_key(X, ModifiersState::ALT, &modes), Some(b"x".to_vec()));
+        modes.set(Mode::MetaSendsEscape, true);
+        assert_eq!(encode_key(X, ModifiersState::ALT, &modes), Some(b"\x1bx".to_vec()));
+    }
+}
@@ -1,14 +1,28 @@
-use characters::InputChar;
+use std::{env, path::PathBuf, process::Command};
 use wgpu::SurfaceError;
-use winit::{dpi::Size, event::{ElementState, Event, KeyboardInput, ModifiersState, WindowEvent}, event_loop::{ControlFlow, EventLoop}, platform::macos::WindowBuilderExtMacOS, window::WindowBuilder};
+use winit::{dpi::Size, event::{ElementState, Event, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode, WindowEvent}, event_loop::{ControlFlow, EventLoop}, platform::macos::WindowBuilderExtMacOS, window::WindowBuilder};
 
+mod attributes;
+mod clipboard;
 mod constants;
 mod cursor;
 mod characters;
+mod charset;
 mod screen;
+mod parser;
+mod modes;
+mod palette;
 mod terminal;
 mod device;
+mod hyperlink;
+mod selection;
+mod tabs;
+mod mouse;
+mod keyboard;
 
+use clipboard::Selection;
+use constants::{LINK_OPENER, NEXT_PROMPT_KEY, PASTE_KEY, PREVIOUS_PROMPT_KEY, TERMINAL_NAME};
+use hyperlink::{CommandOpener, Opener};
 use terminal::Terminal;
 use device::{Shell, CustomEvent};
 
@@ -19,7 +33,7 @@ fn main() {
     let window = WindowBuilder::new()
         .with_titlebar_transparent(true)
         .with_fullsize_content_view(true)
-        .with_title_hidden(true)
+        .with_title(TERMINAL_NAME)
         .with_resizable(false)
         .with_inner_size(winit::dpi::LogicalSize::new(769.0, 510.0))
         .build(&event_loop)
@@ -28,15 +42,23 @@ fn main() {
     let proxy = event_loop.create_proxy();
 
     let mut terminal = pollster::block_on(Terminal::new(&window));
-    let mut shell = Shell::new(proxy);
+    let mut shell = Shell::new(proxy, env::current_dir().ok().as_deref());
 
     let mut modifiers: ModifiersState = ModifiersState::default();
+    let opener: Box<dyn Opener> = Box::new(CommandOpener::new(LINK_OPENER));
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::UserEvent(event) => {
             match event {
-                CustomEvent::StdOut(mut data) => {
-                    terminal.set_data(&mut data);
+                CustomEvent::StdOut(data) => {
+                    terminal.set_data(&data);
+                    if let Some(title) = terminal.take_title_change() {
+                        window.set_title(&title);
+                    }
+                    let responses = terminal.take_responses();
+                    if !responses.is_empty() {
+                        shell.send(&responses);
+                    }
                 },
                 CustomEvent::Terminate => {
                     *control_flow = ControlFlow::Exit;
@@ -55,6 +77,32 @@ fn main() {
             },
             WindowEvent::ModifiersChanged(current_modifiers) => modifiers = *current_modifiers,
             WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::Focused(focused) => {
+                if let Some(report) = terminal.set_focus(*focused) {
+                    shell.send(&report);
+                }
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                if let Some(report) = terminal.set_mouse_position(*position, modifiers) {
+                    shell.send(&report);
+                }
+            },
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match terminal.hovered_uri() {
+                    Some(uri) if pressed && *button == MouseButton::Left && modifiers.ctrl() => opener.open(&uri),
+                    _ => {
+                        if let Some(report) = terminal.mouse_input(*button, pressed, modifiers) {
+                            shell.send(&report);
+                        }
+                    }
+                }
+            },
+            WindowEvent::MouseWheel { delta, .. } => {
+                if let Some(report) = terminal.mouse_wheel(*delta, modifiers) {
+                    shell.send(&report);
+                }
+            },
             WindowEvent::KeyboardInput {
                 input: KeyboardInput {
                     state: key_state,
@@ -64,8 +112,26 @@ fn main() {
                 ..
             } => {
                 if *key_state == ElementState::Pressed {
-                    if let Some(c) = InputChar::from(*key, modifiers) {
-                        shell.send(&[c as u8]);
+                    let shortcut = modifiers.ctrl() && modifiers.shift();
+                    match key {
+                        key if *key == PREVIOUS_PROMPT_KEY && shortcut => terminal.scroll_to_prompt(true),
+                        key if *key == NEXT_PROMPT_KEY && shortcut => terminal.scroll_to_prompt(false),
+                        VirtualKeyCode::O if shortcut => terminal.copy_last_output(),
+                        VirtualKeyCode::N if shortcut => {
+                            new_window(terminal.cwd.clone().filter(|cwd| cwd.is_dir()).or_else(|| shell.cwd()));
+                        },
+                        key if *key == PASTE_KEY && shortcut => {
+                            if let Some(text) = terminal.clipboard.load(Selection::Clipboard) {
+                                terminal.scroll_to_bottom();
+                                shell.send(&terminal.paste_data(&text));
+                            }
+                        },
+                        _ => {
+                            if let Some(bytes) = keyboard::encode_key(*key, modifiers, &terminal.modes) {
+                                terminal.scroll_to_bottom();
+                                shell.send(&bytes);
+                            }
+                        }
                     }
                 }
             },
@@ -85,3 +151,22 @@ fn main() {
         _ => {}
     });
 }
+
+// Start another instance of snarkyterm, its shell is spawned in the given
+// directory or in the current one
+fn new_window(cwd: Option<PathBuf>) {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(err) => {
+            println!("Failed to open a new window: {}", err);
+            return;
+        }
+    };
+    let mut command = Command::new(exe);
+    if let Some(cwd) = cwd {
+        command.current_dir(cwd);
+    }
+    if let Err(err) = command.spawn() {
+        println!("Failed to open a new window: {}", err);
+    }
+}
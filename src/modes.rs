@@ -0,0 +1,124 @@
+use std::collections::{HashMap, HashSet};
+
+// REF: https://vt100.net/docs/vt510-rm/DECRQM.html
+// REF: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Functions-using-CSI-_-ordered-by-the-final-character_s_
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    // ANSI modes, set with `CSI Pm h`
+    Insert,
+    LinefeedNewline,
+    // DEC private modes, set with `CSI ? Pm h`
+    CursorKeys,
+    ReverseVideo,
+    Origin,
+    Autowrap,
+    CursorVisible,
+    AltScreen,
+    AltScreenClear,
+    AltScreenSaveCursor,
+    BracketedPaste,
+    MouseX10,
+    MouseNormal,
+    MouseButtonEvent,
+    MouseAnyEvent,
+    MouseUtf8,
+    MouseSgr,
+    MouseUrxvt,
+    MouseSgrPixel,
+    FocusReporting,
+    SynchronizedOutput,
+    KeypadApplication,
+    MetaSendsEscape,
+    AltSendsEscape
+}
+
+// Only one mode of each group is enabled at a time
+pub const MOUSE_TRACKING_MODES: [Mode; 4] = [Mode::MouseX10, Mode::MouseNormal, Mode::MouseButtonEvent, Mode::MouseAnyEvent];
+pub const MOUSE_ENCODING_MODES: [Mode; 4] = [Mode::MouseUtf8, Mode::MouseSgr, Mode::MouseUrxvt, Mode::MouseSgrPixel];
+
+impl Mode {
+    pub fn from_ansi(mode: u16) -> Option<Mode> {
+        match mode {
+            4 => Some(Mode::Insert),
+            20 => Some(Mode::LinefeedNewline),
+            _ => None
+        }
+    }
+
+    pub fn from_private(mode: u16) -> Option<Mode> {
+        match mode {
+            1 => Some(Mode::CursorKeys),
+            5 => Some(Mode::ReverseVideo),
+            6 => Some(Mode::Origin),
+            7 => Some(Mode::Autowrap),
+            9 => Some(Mode::MouseX10),
+            25 => Some(Mode::CursorVisible),
+            47 => Some(Mode::AltScreen),
+            66 => Some(Mode::KeypadApplication),
+            1047 => Some(Mode::AltScreenClear),
+            1000 => Some(Mode::MouseNormal),
+            1002 => Some(Mode::MouseButtonEvent),
+            1003 => Some(Mode::MouseAnyEvent),
+            1004 => Some(Mode::FocusReporting),
+            1005 => Some(Mode::MouseUtf8),
+            1006 => Some(Mode::MouseSgr),
+            1015 => Some(Mode::MouseUrxvt),
+            1016 => Some(Mode::MouseSgrPixel),
+            1036 => Some(Mode::MetaSendsEscape),
+            1039 => Some(Mode::AltSendsEscape),
+            1049 => Some(Mode::AltScreenSaveCursor),
+            2004 => Some(Mode::BracketedPaste),
+            2026 => Some(Mode::SynchronizedOutput),
+            _ => None
+        }
+    }
+}
+
+pub struct Modes {
+    enabled: HashSet<Mode>,
+    saved: HashMap<Mode, bool>
+}
+
+impl Modes {
+    pub fn new() -> Self {
+        let mut enabled = HashSet::new();
+        enabled.insert(Mode::Autowrap);
+        enabled.insert(Mode::CursorVisible);
+        enabled.insert(Mode::AltSendsEscape);
+        Self {
+            enabled,
+            saved: HashMap::new()
+        }
+    }
+
+    pub fn get(&self, mode: Mode) -> bool {
+        self.enabled.contains(&mode)
+    }
+
+    pub fn set(&mut self, mode: Mode, enabled: bool) {
+        if enabled {
+            self.enabled.insert(mode);
+        } else {
+            self.enabled.remove(&mode);
+        }
+    }
+
+    pub fn save(&mut self, mode: Mode) {
+        self.saved.insert(mode, self.get(mode));
+    }
+
+    pub fn saved(&self, mode: Mode) -> Option<bool> {
+        self.saved.get(&mode).cloned()
+    }
+
+    // The mode value of a DECRPM reply: 1 for set, 2 for reset,
+    // unrecognized modes are reported as 0
+    pub fn report(&self, mode: Option<Mode>) -> u8 {
+        match mode {
+            Some(mode) if self.get(mode) => 1,
+            Some(_) => 2,
+            None => 0
+        }
+    }
+}
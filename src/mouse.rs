@@ -0,0 +1,95 @@
+use winit::event::ModifiersState;
+
+use crate::modes::Mode;
+
+// REF: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Mouse-Tracking
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Button {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseEventKind {
+    Press,
+    Release,
+    Motion
+}
+
+pub struct MouseEvent {
+    pub kind: MouseEventKind,
+    // The button pressed or released, or held down during a motion
+    pub button: Option<Button>,
+    pub modifiers: ModifiersState,
+    // Position as (row, column)
+    pub cell: (usize, usize),
+    // Position in pixels from the top left corner of the screen, as (x, y)
+    pub pixel: (usize, usize)
+}
+
+impl MouseEvent {
+    // Only the SGR encodings can tell which button was released
+    fn code(&self, sgr: bool) -> u32 {
+        let mut code = match (self.kind, self.button) {
+            (MouseEventKind::Release, _) if !sgr => 3,
+            (_, Some(Button::Left)) => 0,
+            (_, Some(Button::Middle)) => 1,
+            (_, Some(Button::Right)) => 2,
+            (_, Some(Button::WheelUp)) => 64,
+            (_, Some(Button::WheelDown)) => 65,
+            (_, None) => 3
+        };
+        if self.modifiers.shift() {
+            code += 4;
+        }
+        if self.modifiers.alt() {
+            code += 8;
+        }
+        if self.modifiers.ctrl() {
+            code += 16;
+        }
+        if self.kind == MouseEventKind::Motion {
+            code += 32;
+        }
+        code
+    }
+
+    // The report sent to the application with the given coordinate encoding,
+    // nothing is sent when the position can't be encoded
+    pub fn encode(&self, encoding: Option<Mode>) -> Option<Vec<u8>> {
+        let sgr = matches!(encoding, Some(Mode::MouseSgr) | Some(Mode::MouseSgrPixel));
+        let code = self.code(sgr);
+        let (x, y) = if encoding == Some(Mode::MouseSgrPixel) { self.pixel } else { (self.cell.1, self.cell.0) };
+        let (x, y) = (x as u32 + 1, y as u32 + 1);
+        match encoding {
+            Some(Mode::MouseSgr) | Some(Mode::MouseSgrPixel) => {
+                let action = if self.kind == MouseEventKind::Release { 'm' } else { 'M' };
+                Some(format!("\x1b[<{};{};{}{}", code, x, y, action).into_bytes())
+            },
+            Some(Mode::MouseUrxvt) => Some(format!("\x1b[{};{};{}M", code + 32, x, y).into_bytes()),
+            // Values are sent as UTF-8 characters, going up to 2047
+            Some(Mode::MouseUtf8) => {
+                let mut report = String::from("\x1b[M");
+                for value in [code, x, y] {
+                    report.push(char::from_u32(value + 32).filter(|c| (*c as u32) < 0x800)?);
+                }
+                Some(report.into_bytes())
+            },
+            // Values are sent as single bytes, going up to 223
+            _ => {
+                let mut report = b"\x1b[M".to_vec();
+                for value in [code, x, y] {
+                    if value + 32 > 0xFF {
+                        return None;
+                    }
+                    report.push((value + 32) as u8);
+                }
+                Some(report)
+            }
+        }
+    }
+}
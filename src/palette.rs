@@ -0,0 +1,103 @@
+// REF: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Operating-System-Commands
+
+pub const DEFAULT_FOREGROUND: [u8; 3] = [0xff, 0xff, 0xff];
+pub const DEFAULT_BACKGROUND: [u8; 3] = [0x04, 0x04, 0x04];
+pub const DEFAULT_CURSOR: [u8; 3] = [0xff, 0x00, 0x00];
+
+const ANSI_COLORS: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], [0xcd, 0x00, 0x00], [0x00, 0xcd, 0x00], [0xcd, 0xcd, 0x00],
+    [0x00, 0x00, 0xee], [0xcd, 0x00, 0xcd], [0x00, 0xcd, 0xcd], [0xe5, 0xe5, 0xe5],
+    [0x7f, 0x7f, 0x7f], [0xff, 0x00, 0x00], [0x00, 0xff, 0x00], [0xff, 0xff, 0x00],
+    [0x5c, 0x5c, 0xff], [0xff, 0x00, 0xff], [0x00, 0xff, 0xff], [0xff, 0xff, 0xff]
+];
+
+// The xterm 256 colors table: 16 ANSI colors, a 6x6x6 color cube and 24 shades of gray
+fn default_color(index: u8) -> [u8; 3] {
+    match index {
+        0..=15 => ANSI_COLORS[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            [level(i / 36), level((i / 6) % 6), level(i % 6)]
+        },
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            [gray, gray, gray]
+        }
+    }
+}
+
+// The colors that programs can change at runtime with OSC 4/10/11/12
+pub struct Palette {
+    colors: Vec<[u8; 3]>,
+    pub foreground: [u8; 3],
+    pub background: [u8; 3],
+    pub cursor: [u8; 3]
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self {
+            colors: (0..=255).map(default_color).collect(),
+            foreground: DEFAULT_FOREGROUND,
+            background: DEFAULT_BACKGROUND,
+            cursor: DEFAULT_CURSOR
+        }
+    }
+
+    pub fn color(&self, index: u8) -> [u8; 3] {
+        self.colors[index as usize]
+    }
+
+    pub fn set_color(&mut self, index: u8, color: [u8; 3]) {
+        self.colors[index as usize] = color;
+    }
+
+    pub fn reset_color(&mut self, index: u8) {
+        self.colors[index as usize] = default_color(index);
+    }
+
+    pub fn reset_colors(&mut self) {
+        self.colors = (0..=255).map(default_color).collect();
+    }
+}
+
+// Parse an X11 color spec, in the form of `rgb:r/g/b` with 1 to 4 hex digits
+// per component, or `#rgb` with the same number of digits for each component
+pub fn parse_color(spec: &str) -> Option<[u8; 3]> {
+    if let Some(rgb) = spec.strip_prefix("rgb:") {
+        let parts: Vec<&str> = rgb.split('/').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        let mut color = [0u8; 3];
+        for (i, part) in parts.iter().enumerate() {
+            if !part.is_ascii() || part.is_empty() || part.len() > 4 {
+                return None;
+            }
+            let value = u32::from_str_radix(part, 16).ok()?;
+            let max = (1u32 << (4 * part.len())) - 1;
+            color[i] = (value * 255 / max) as u8;
+        }
+        Some(color)
+    } else if let Some(hex) = spec.strip_prefix('#') {
+        if !hex.is_ascii() || hex.is_empty() || hex.len() % 3 != 0 || hex.len() > 12 {
+            return None;
+        }
+        let digits = hex.len() / 3;
+        let mut color = [0u8; 3];
+        for i in 0..3 {
+            let value = u32::from_str_radix(&hex[i * digits..(i + 1) * digits], 16).ok()?;
+            // Only the most significant bits are used, `#fff` is the same as `#f0f0f0`
+            color[i] = if digits == 1 { value << 4 } else { value >> (4 * digits - 8) } as u8;
+        }
+        Some(color)
+    } else {
+        None
+    }
+}
+
+pub fn format_color(color: [u8; 3]) -> String {
+    let [r, g, b] = color;
+    format!("rgb:{:04x}/{:04x}/{:04x}", r as u16 * 257, g as u16 * 257, b as u16 * 257)
+}
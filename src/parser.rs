@@ -0,0 +1,554 @@
+// REF: https://vt100.net/emu/dec_ansi_parser
+//
+// A state machine parser for the VT500 family escape sequences. The parser
+// keeps its state between calls, so a sequence split across several reads
+// from the shell is picked up where the previous chunk left off. Printable
+// text is decoded as UTF-8, with the same guarantee for multi-byte chars.
+
+const MAX_INTERMEDIATES: usize = 2;
+const MAX_PARAMS: usize = 32;
+// Large enough for the clipboard content of OSC 52
+const MAX_OSC_LEN: usize = 1 << 20;
+const REPLACEMENT_CHAR: char = '\u{FFFD}';
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    DcsEntry,
+    DcsParam,
+    DcsIntermediate,
+    DcsPassthrough,
+    DcsIgnore,
+    OscString,
+    SosPmApcString
+}
+
+// Numeric parameters of a CSI or DCS sequence, each parameter can carry
+// colon separated sub-parameters, e.g. `38:2::255:0:0` is a single parameter.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Params(Vec<Vec<u16>>);
+
+impl Params {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Get the first value of a parameter, missing or zero values fall back to
+    // the default value, as most of the sequences treat 0 as the default.
+    pub fn get(&self, index: usize, default: u16) -> u16 {
+        match self.0.get(index).and_then(|p| p.first()) {
+            Some(&value) if value > 0 => value,
+            _ => default
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Vec<u16>> {
+        self.0.iter()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Print(char),
+    Execute(u8),
+    CsiDispatch {
+        params: Params,
+        intermediates: Vec<u8>,
+        ignore: bool,
+        action: char
+    },
+    EscDispatch {
+        intermediates: Vec<u8>,
+        ignore: bool,
+        byte: u8
+    },
+    OscDispatch {
+        params: Vec<Vec<u8>>,
+        bell_terminated: bool
+    },
+    Hook {
+        params: Params,
+        intermediates: Vec<u8>,
+        ignore: bool,
+        action: char
+    },
+    Put(u8),
+    Unhook
+}
+
+pub struct Parser {
+    state: State,
+    intermediates: Vec<u8>,
+    params: Vec<Vec<u16>>,
+    param: Vec<u16>,
+    ignoring: bool,
+    osc: Vec<u8>,
+    // Set when the OSC string is too long, it's dropped instead of dispatched
+    osc_overflow: bool,
+    utf8: Vec<u8>,
+    utf8_len: usize,
+    actions: Vec<Action>
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            intermediates: vec![],
+            params: vec![],
+            param: vec![],
+            ignoring: false,
+            osc: vec![],
+            osc_overflow: false,
+            utf8: vec![],
+            utf8_len: 0,
+            actions: vec![]
+        }
+    }
+
+    pub fn parse(&mut self, buf: &[u8]) -> Vec<Action> {
+        for &byte in buf {
+            self.advance(byte);
+        }
+        std::mem::take(&mut self.actions)
+    }
+
+    fn advance(&mut self, byte: u8) {
+        if self.state == State::Ground && byte >= 0x80 {
+            self.utf8_byte(byte);
+            return;
+        }
+        if !self.utf8.is_empty() {
+            self.utf8_invalid();
+        }
+
+        // Transitions that can happen from any state
+        match byte {
+            // CAN and SUB cancel the OSC string instead of ending it
+            0x18 | 0x1A => {
+                if self.state == State::OscString {
+                    self.osc.clear();
+                } else {
+                    self.leave_string();
+                }
+                self.actions.push(Action::Execute(byte));
+                self.state = State::Ground;
+                return;
+            },
+            0x1B => {
+                self.leave_string();
+                self.clear();
+                self.state = State::Escape;
+                return;
+            },
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => self.ground(byte),
+            State::Escape => self.escape(byte),
+            State::EscapeIntermediate => self.escape_intermediate(byte),
+            State::CsiEntry => self.csi_entry(byte),
+            State::CsiParam => self.csi_param(byte),
+            State::CsiIntermediate => self.csi_intermediate(byte),
+            State::CsiIgnore => self.csi_ignore(byte),
+            State::DcsEntry => self.dcs_entry(byte),
+            State::DcsParam => self.dcs_param(byte),
+            State::DcsIntermediate => self.dcs_intermediate(byte),
+            State::DcsPassthrough => self.dcs_passthrough(byte),
+            State::DcsIgnore => {},
+            State::OscString => self.osc_string(byte),
+            State::SosPmApcString => {}
+        }
+    }
+
+    fn is_execute(byte: u8) -> bool {
+        matches!(byte, 0x00..=0x17 | 0x19 | 0x1C..=0x1F)
+    }
+
+    fn ground(&mut self, byte: u8) {
+        if Self::is_execute(byte) {
+            self.actions.push(Action::Execute(byte));
+        } else if byte != 0x7F {
+            self.actions.push(Action::Print(byte as char));
+        }
+    }
+
+    fn utf8_byte(&mut self, byte: u8) {
+        if self.utf8.is_empty() {
+            self.utf8_len = match byte {
+                0xC2..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF4 => 4,
+                _ => {
+                    self.actions.push(Action::Print(REPLACEMENT_CHAR));
+                    return;
+                }
+            };
+            self.utf8.push(byte);
+            return;
+        }
+
+        // The second byte has a narrower range for some lead bytes, to reject
+        // overlong encodings, surrogates and code points above U+10FFFF.
+        let valid = match (self.utf8.len(), self.utf8[0]) {
+            (1, 0xE0) => (0xA0..=0xBF).contains(&byte),
+            (1, 0xED) => (0x80..=0x9F).contains(&byte),
+            (1, 0xF0) => (0x90..=0xBF).contains(&byte),
+            (1, 0xF4) => (0x80..=0x8F).contains(&byte),
+            _ => (0x80..=0xBF).contains(&byte)
+        };
+        if !valid {
+            self.utf8_invalid();
+            self.utf8_byte(byte);
+            return;
+        }
+
+        self.utf8.push(byte);
+        if self.utf8.len() == self.utf8_len {
+            let c = std::str::from_utf8(&self.utf8).ok()
+                .and_then(|s| s.chars().next())
+                .unwrap_or(REPLACEMENT_CHAR);
+            self.actions.push(Action::Print(c));
+            self.utf8.clear();
+        }
+    }
+
+    // Drop an incomplete UTF-8 sequence, it's shown as a single replacement char
+    fn utf8_invalid(&mut self) {
+        self.utf8.clear();
+        self.actions.push(Action::Print(REPLACEMENT_CHAR));
+    }
+
+    fn escape(&mut self, byte: u8) {
+        match byte {
+            _ if Self::is_execute(byte) => self.actions.push(Action::Execute(byte)),
+            0x20..=0x2F => {
+                self.collect(byte);
+                self.state = State::EscapeIntermediate;
+            },
+            b'[' => {
+                self.clear();
+                self.state = State::CsiEntry;
+            },
+            b']' => {
+                self.osc.clear();
+                self.osc_overflow = false;
+                self.state = State::OscString;
+            },
+            b'P' => {
+                self.clear();
+                self.state = State::DcsEntry;
+            },
+            b'X' | b'^' | b'_' => self.state = State::SosPmApcString,
+            0x30..=0x7E => {
+                self.esc_dispatch(byte);
+                self.state = State::Ground;
+            },
+            _ => {}
+        }
+    }
+
+    fn escape_intermediate(&mut self, byte: u8) {
+        match byte {
+            _ if Self::is_execute(byte) => self.actions.push(Action::Execute(byte)),
+            0x20..=0x2F => self.collect(byte),
+            0x30..=0x7E => {
+                self.esc_dispatch(byte);
+                self.state = State::Ground;
+            },
+            _ => {}
+        }
+    }
+
+    fn csi_entry(&mut self, byte: u8) {
+        match byte {
+            _ if Self::is_execute(byte) => self.actions.push(Action::Execute(byte)),
+            0x20..=0x2F => {
+                self.collect(byte);
+                self.state = State::CsiIntermediate;
+            },
+            0x30..=0x3B => {
+                self.param(byte);
+                self.state = State::CsiParam;
+            },
+            0x3C..=0x3F => {
+                self.collect(byte);
+                self.state = State::CsiParam;
+            },
+            0x40..=0x7E => {
+                self.csi_dispatch(byte);
+                self.state = State::Ground;
+            },
+            _ => {}
+        }
+    }
+
+    fn csi_param(&mut self, byte: u8) {
+        match byte {
+            _ if Self::is_execute(byte) => self.actions.push(Action::Execute(byte)),
+            0x20..=0x2F => {
+                self.collect(byte);
+                self.state = State::CsiIntermediate;
+            },
+            0x30..=0x3B => self.param(byte),
+            0x3C..=0x3F => self.state = State::CsiIgnore,
+            0x40..=0x7E => {
+                self.csi_dispatch(byte);
+                self.state = State::Ground;
+            },
+            _ => {}
+        }
+    }
+
+    fn csi_intermediate(&mut self, byte: u8) {
+        match byte {
+            _ if Self::is_execute(byte) => self.actions.push(Action::Execute(byte)),
+            0x20..=0x2F => self.collect(byte),
+            0x30..=0x3F => self.state = State::CsiIgnore,
+            0x40..=0x7E => {
+                self.csi_dispatch(byte);
+                self.state = State::Ground;
+            },
+            _ => {}
+        }
+    }
+
+    fn csi_ignore(&mut self, byte: u8) {
+        match byte {
+            _ if Self::is_execute(byte) => self.actions.push(Action::Execute(byte)),
+            0x40..=0x7E => self.state = State::Ground,
+            _ => {}
+        }
+    }
+
+    fn dcs_entry(&mut self, byte: u8) {
+        match byte {
+            0x20..=0x2F => {
+                self.collect(byte);
+                self.state = State::DcsIntermediate;
+            },
+            0x30..=0x3B => {
+                self.param(byte);
+                self.state = State::DcsParam;
+            },
+            0x3C..=0x3F => {
+                self.collect(byte);
+                self.state = State::DcsParam;
+            },
+            0x40..=0x7E => self.hook(byte),
+            _ => {}
+        }
+    }
+
+    fn dcs_param(&mut self, byte: u8) {
+        match byte {
+            0x20..=0x2F => {
+                self.collect(byte);
+                self.state = State::DcsIntermediate;
+            },
+            0x30..=0x3B => self.param(byte),
+            0x3C..=0x3F => self.state = State::DcsIgnore,
+            0x40..=0x7E => self.hook(byte),
+            _ => {}
+        }
+    }
+
+    fn dcs_intermediate(&mut self, byte: u8) {
+        match byte {
+            0x20..=0x2F => self.collect(byte),
+            0x30..=0x3F => self.state = State::DcsIgnore,
+            0x40..=0x7E => self.hook(byte),
+            _ => {}
+        }
+    }
+
+    fn dcs_passthrough(&mut self, byte: u8) {
+        if byte != 0x7F {
+            self.actions.push(Action::Put(byte));
+        }
+    }
+
+    fn osc_string(&mut self, byte: u8) {
+        match byte {
+            0x07 => {
+                self.osc_dispatch(true);
+                self.state = State::Ground;
+            },
+            0x00..=0x1F => {},
+            _ => {
+                if self.osc.len() < MAX_OSC_LEN {
+                    self.osc.push(byte);
+                } else {
+                    self.osc_overflow = true;
+                }
+            }
+        }
+    }
+
+    // Terminate any string in progress when the parser is interrupted by an
+    // ESC (the first half of ST), or by CAN or SUB for a DCS string.
+    fn leave_string(&mut self) {
+        match self.state {
+            State::OscString => self.osc_dispatch(false),
+            State::DcsPassthrough => self.actions.push(Action::Unhook),
+            _ => {}
+        }
+    }
+
+    fn clear(&mut self) {
+        self.intermediates.clear();
+        self.params.clear();
+        self.param.clear();
+        self.ignoring = false;
+    }
+
+    fn collect(&mut self, byte: u8) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(byte);
+        } else {
+            self.ignoring = true;
+        }
+    }
+
+    // The current parameter always ends with the sub-parameter being read,
+    // so digits accumulate into its last value and `:` opens a new one.
+    fn param(&mut self, byte: u8) {
+        if self.params.len() >= MAX_PARAMS {
+            self.ignoring = true;
+            return;
+        }
+        if self.param.is_empty() {
+            self.param.push(0);
+        }
+        match byte {
+            b';' => self.params.push(std::mem::take(&mut self.param)),
+            b':' => self.param.push(0),
+            _ => {
+                let last = self.param.last_mut().unwrap();
+                *last = last.saturating_mul(10).saturating_add((byte - b'0') as u16);
+            }
+        }
+    }
+
+    fn take_params(&mut self) -> Params {
+        if !self.param.is_empty() || !self.params.is_empty() {
+            if self.param.is_empty() {
+                self.param.push(0);
+            }
+            self.params.push(std::mem::take(&mut self.param));
+        }
+        Params(std::mem::take(&mut self.params))
+    }
+
+    fn esc_dispatch(&mut self, byte: u8) {
+        self.actions.push(Action::EscDispatch {
+            intermediates: std::mem::take(&mut self.intermediates),
+            ignore: self.ignoring,
+            byte
+        });
+    }
+
+    fn csi_dispatch(&mut self, byte: u8) {
+        let params = self.take_params();
+        self.actions.push(Action::CsiDispatch {
+            params,
+            intermediates: std::mem::take(&mut self.intermediates),
+            ignore: self.ignoring,
+            action: byte as char
+        });
+    }
+
+    fn hook(&mut self, byte: u8) {
+        let params = self.take_params();
+        self.actions.push(Action::Hook {
+            params,
+            intermediates: std::mem::take(&mut self.intermediates),
+            ignore: self.ignoring,
+            action: byte as char
+        });
+        self.state = State::DcsPassthrough;
+    }
+
+    fn osc_dispatch(&mut self, bell_terminated: bool) {
+        if std::mem::take(&mut self.osc_overflow) {
+            self.osc.clear();
+            return;
+        }
+        let params = self.osc.split(|&c| c == b';').map(|p| p.to_vec()).collect();
+        self.osc.clear();
+        self.actions.push(Action::OscDispatch { params, bell_terminated });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn csi(params: Vec<Vec<u16>>, action: char) -> Action {
+        Action::CsiDispatch { params: Params(params), intermediates: vec![], ignore: false, action }
+    }
+
+    #[test]
+    fn csi_split_mid_parameter() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"\x1b[1"), vec![]);
+        assert_eq!(parser.parse(b"2;3"), vec![]);
+        assert_eq!(parser.parse(b"Hx"), vec![csi(vec![vec![12], vec![3]], 'H'), Action::Print('x')]);
+    }
+
+    #[test]
+    fn utf8_split_across_calls() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"a\xC3"), vec![Action::Print('a')]);
+        assert_eq!(parser.parse(b"\xA9"), vec![Action::Print('é')]);
+        assert_eq!(parser.parse(b"\xE4"), vec![]);
+        assert_eq!(parser.parse(b"\xB8"), vec![]);
+        assert_eq!(parser.parse(b"\xAD"), vec![Action::Print('中')]);
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"\xC3a"), vec![Action::Print(REPLACEMENT_CHAR), Action::Print('a')]);
+    }
+
+    #[test]
+    fn osc_terminated_by_bell() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"\x1b]0;ti"), vec![]);
+        assert_eq!(parser.parse(b"tle\x07"), vec![
+            Action::OscDispatch { params: vec![b"0".to_vec(), b"title".to_vec()], bell_terminated: true }
+        ]);
+    }
+
+    #[test]
+    fn osc_terminated_by_st() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"\x1b]2;title\x1b\\"), vec![
+            Action::OscDispatch { params: vec![b"2".to_vec(), b"title".to_vec()], bell_terminated: false },
+            Action::EscDispatch { intermediates: vec![], ignore: false, byte: b'\\' }
+        ]);
+    }
+
+    #[test]
+    fn cancel_aborts_sequence() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"\x1b[12\x18A"), vec![Action::Execute(0x18), Action::Print('A')]);
+        assert_eq!(parser.parse(b"\x1b]0;title\x1aB"), vec![Action::Execute(0x1A), Action::Print('B')]);
+    }
+
+    #[test]
+    fn colon_sub_parameters() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(b"\x1b[38:2::10:20:30;1m"), vec![
+            csi(vec![vec![38, 2, 0, 10, 20, 30], vec![1]], 'm')
+        ]);
+    }
+}
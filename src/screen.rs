@@ -1,44 +1,303 @@
+use unicode_width::UnicodeWidthChar;
+
+use crate::{attributes::Attributes, constants::AMBIGUOUS_WIDE};
+
+// REF: https://www.unicode.org/reports/tr11/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cell {
+    pub c: char,
+    pub attrs: Attributes,
+    // Index in the terminal's hyperlink table
+    pub link: Option<u32>,
+    // 2 for a wide character, 0 for the spacer cell following it
+    pub width: u8,
+    // Index in the buffer's table of zero width characters following `c`
+    pub combining: Option<u32>
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self { c: '\0', attrs: Attributes::default(), link: None, width: 1, combining: None }
+    }
+}
+
+impl Cell {
+    // An erased cell only keeps the background color of the current attributes
+    pub fn blank(attrs: &Attributes) -> Self {
+        Self { attrs: Attributes { bg: attrs.bg, ..Attributes::default() }, ..Cell::default() }
+    }
+}
+
+// The number of cells taken by a character, from its East Asian Width
+pub fn char_width(c: char) -> usize {
+    let width = if AMBIGUOUS_WIDE { c.width_cjk() } else { c.width() };
+    width.unwrap_or(1)
+}
+
+// Shell integration marks, from OSC 133
+// REF: https://gitlab.freedesktop.org/Per_Bothner/specifications/blob/master/proposals/semantic-prompts.md
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mark {
+    PromptStart,
+    CommandStart,
+    OutputStart,
+    CommandEnd(Option<i32>)
+}
+
+// The buffer keeps every line printed so far, the visible screen is the last
+// `height` lines starting from `start_line`, rows passed in are relative to it.
+// A buffer without history (like the alternate screen) drops the scrolled lines.
+// Lines are counted from the top of the history when it's not about the screen.
 pub struct ScreenBuffer {
-    data: Vec<u8>,
+    data: Vec<Cell>,
     width: usize,
     height: usize,
+    start_line: usize,
+    history: bool,
+    // How many lines the display is scrolled back into the history
+    display_offset: usize,
+    // Marks as (line, column, mark), sorted by line
+    marks: Vec<(usize, usize, Mark)>,
+    // Combining marks and joined characters appended to cells
+    combining: Vec<String>,
 }
 
 impl ScreenBuffer {
-    pub fn new(width: usize, height: usize) -> Self {
+    pub fn new(width: usize, height: usize, history: bool) -> Self {
         Self {
-            data: vec![0; width * height],
+            data: vec![Cell::default(); width * height],
             width,
             height,
+            start_line: 0,
+            history,
+            display_offset: 0,
+            marks: vec![],
+            combining: vec![],
         }
     }
 
     fn expand_buffer(&mut self) {
-        self.data.append(&mut vec![0; self.width * self.height]);
+        self.data.append(&mut vec![Cell::default(); self.width * self.height]);
     }
 
     pub fn lines_count(&self) -> usize {
         self.data.len() / self.width + 1
     }
 
-    pub fn clear(&mut self) {
-        self.data = vec![0; self.width * self.height];
+    // Drop every line above the visible screen
+    pub fn clear_history(&mut self) {
+        self.data.drain(0..self.start_line * self.width);
+        let start_line = self.start_line;
+        self.marks.retain(|(line, _, _)| *line >= start_line);
+        for (line, _, _) in self.marks.iter_mut() {
+            *line -= start_line;
+        }
+        self.start_line = 0;
+        self.display_offset = 0;
+    }
+
+    // Scroll the lines between top and bottom (inclusive) up, when the region
+    // covers the whole screen, the lines scrolled out go into the history
+    pub fn scroll_up(&mut self, top: usize, bottom: usize, count: usize, blank: Cell) {
+        if self.history && top == 0 && bottom == self.height - 1 {
+            for _ in 0..count.min(self.height) {
+                self.start_line += 1;
+                // Keep the display on the same lines when it's looking at the history
+                if self.display_offset > 0 {
+                    self.display_offset += 1;
+                }
+                if (self.start_line + self.height) * self.width > self.data.len() {
+                    self.expand_buffer();
+                }
+                self.row_mut(self.height - 1).fill(blank);
+            }
+        } else {
+            self.delete_lines(top, bottom, count, blank);
+        }
+    }
+
+    pub fn scroll_down(&mut self, top: usize, bottom: usize, count: usize, blank: Cell) {
+        self.insert_lines(top, bottom, count, blank);
+    }
+
+    fn row_mut(&mut self, row: usize) -> &mut [Cell] {
+        let start = (self.start_line + row) * self.width;
+        &mut self.data[start..start + self.width]
+    }
+
+    // Fill the cells in the range [from, to) of a row with blank cells
+    pub fn erase(&mut self, row: usize, from: usize, to: usize, blank: Cell) {
+        let to = to.min(self.width);
+        if from < to {
+            self.split_wide_char(row, from, blank);
+            self.split_wide_char(row, to, blank);
+            self.row_mut(row)[from..to].fill(blank);
+        }
+    }
+
+    // A wide character going across the left edge of a column can't be kept
+    // in halves, both of its cells are erased
+    pub fn split_wide_char(&mut self, row: usize, col: usize, blank: Cell) {
+        if col > 0 && col < self.width && self.get_cell_at(row, col).width == 0 {
+            self.set_cell_at(blank, row, col - 1);
+            self.set_cell_at(blank, row, col);
+        }
+    }
+
+    pub fn insert_cells(&mut self, row: usize, col: usize, count: usize, blank: Cell) {
+        let count = count.min(self.width - col);
+        self.split_wide_char(row, col, blank);
+        self.split_wide_char(row, self.width - count, blank);
+        let line = self.row_mut(row);
+        line[col..].rotate_right(count);
+        line[col..col + count].fill(blank);
+    }
+
+    pub fn delete_cells(&mut self, row: usize, col: usize, count: usize, blank: Cell) {
+        let count = count.min(self.width - col);
+        self.split_wide_char(row, col, blank);
+        self.split_wide_char(row, col + count, blank);
+        let width = self.width;
+        let line = self.row_mut(row);
+        line[col..].rotate_left(count);
+        line[width - count..].fill(blank);
+    }
+
+    // Insert blank lines at a row, the lines below are pushed down and the ones
+    // going past the bottom row are lost
+    pub fn insert_lines(&mut self, row: usize, bottom: usize, count: usize, blank: Cell) {
+        let count = count.min(bottom + 1 - row);
+        let start = (self.start_line + row) * self.width;
+        let end = (self.start_line + bottom + 1) * self.width;
+        self.data[start..end].rotate_right(count * self.width);
+        self.data[start..start + count * self.width].fill(blank);
+        self.shift_marks(row, bottom, count as isize);
+    }
+
+    pub fn delete_lines(&mut self, row: usize, bottom: usize, count: usize, blank: Cell) {
+        let count = count.min(bottom + 1 - row);
+        let start = (self.start_line + row) * self.width;
+        let end = (self.start_line + bottom + 1) * self.width;
+        self.data[start..end].rotate_left(count * self.width);
+        self.data[end - count * self.width..end].fill(blank);
+        self.shift_marks(row, bottom, -(count as isize));
+    }
+
+    // Move the marks of the rows in [top, bottom] along with their lines, the
+    // marks of the lines going out of these rows are dropped
+    fn shift_marks(&mut self, top: usize, bottom: usize, offset: isize) {
+        let (top, bottom) = ((self.start_line + top) as isize, (self.start_line + bottom) as isize);
+        self.marks.retain_mut(|(line, _, _)| {
+            let current = *line as isize;
+            if current < top || current > bottom {
+                return true;
+            }
+            let moved = current + offset;
+            *line = moved.max(0) as usize;
+            moved >= top && moved <= bottom
+        });
+    }
+
+    pub fn add_mark(&mut self, row: usize, col: usize, mark: Mark) {
+        self.marks.push((self.start_line + row, col, mark));
+        self.marks.sort_by_key(|(line, _, _)| *line);
+    }
+
+    pub fn marks(&self) -> &[(usize, usize, Mark)] {
+        &self.marks
+    }
+
+    pub fn start_line(&self) -> usize {
+        self.start_line
+    }
+
+    pub fn display_offset(&self) -> usize {
+        self.display_offset
+    }
+
+    // Scroll the display so that a line is at the top, as far as the history goes
+    pub fn scroll_display_to(&mut self, line: usize) {
+        self.display_offset = self.start_line.saturating_sub(line);
     }
 
-    pub fn set_char_at(&mut self, c: u8, row: usize, col: usize) {
-        let index = row * self.width + col;
+    pub fn reset_display(&mut self) {
+        self.display_offset = 0;
+    }
+
+    // The line shown at a row of the display
+    pub fn display_line(&self, row: usize) -> usize {
+        self.start_line - self.display_offset + row
+    }
+
+    // The hyperlinks referred to by any cell
+    pub fn links(&self) -> impl Iterator<Item = u32> + '_ {
+        self.data.iter().filter_map(|cell| cell.link)
+    }
+
+    pub fn get_line_cell(&self, line: usize, col: usize) -> Cell {
+        self.data.get(line * self.width + col).cloned().unwrap_or_default()
+    }
+
+    // The text between two positions (inclusive), with the trailing blanks of
+    // every line trimmed
+    pub fn text(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let mut lines = vec![];
+        for line in start.0..=end.0 {
+            let from = if line == start.0 { start.1 } else { 0 };
+            let to = if line == end.0 { end.1 + 1 } else { self.width };
+            let text: String = (from..to.min(self.width))
+                .map(|col| self.get_line_cell(line, col))
+                .filter(|cell| cell.width > 0)
+                .map(|cell| if cell.c == '\0' { " ".to_string() } else { self.grapheme(&cell) })
+                .collect();
+            lines.push(text.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    pub fn set_cell_at(&mut self, cell: Cell, row: usize, col: usize) {
+        let index = (self.start_line + row) * self.width + col;
         if index >= self.data.len() {
             self.expand_buffer();
         }
-        self.data[index] = c;
+        self.data[index] = cell;
+    }
+
+    // Append a zero width character to the grapheme of a cell
+    pub fn append_to_cell(&mut self, row: usize, col: usize, c: char) {
+        let mut cell = self.get_cell_at(row, col);
+        let mut combining = cell.combining
+            .and_then(|index| self.combining.get(index as usize))
+            .cloned()
+            .unwrap_or_default();
+        combining.push(c);
+        let index = match self.combining.iter().position(|s| *s == combining) {
+            Some(index) => index,
+            None => {
+                self.combining.push(combining);
+                self.combining.len() - 1
+            }
+        };
+        cell.combining = Some(index as u32);
+        self.set_cell_at(cell, row, col);
+    }
+
+    // The character of a cell with the zero width characters following it
+    pub fn grapheme(&self, cell: &Cell) -> String {
+        let mut grapheme = cell.c.to_string();
+        if let Some(combining) = cell.combining.and_then(|index| self.combining.get(index as usize)) {
+            grapheme.push_str(combining);
+        }
+        grapheme
     }
 
-    pub fn get_char_at(&self, row: usize, col: usize) -> u8 {
-        let index = row * self.width + col;
+    pub fn get_cell_at(&self, row: usize, col: usize) -> Cell {
+        let index = (self.start_line + row) * self.width + col;
         if index < self.data.len() {
             return self.data[index];
         } else {
-            return 0;
+            return Cell::default();
         }
     }
 }
@@ -0,0 +1,21 @@
+// A range of text in the screen buffer, as (line, column) positions counted
+// from the top of the history, both ends are included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextSelection {
+    pub start: (usize, usize),
+    pub end: (usize, usize)
+}
+
+impl TextSelection {
+    pub fn new(from: (usize, usize), to: (usize, usize)) -> Self {
+        if from <= to {
+            Self { start: from, end: to }
+        } else {
+            Self { start: to, end: from }
+        }
+    }
+
+    pub fn contains(&self, line: usize, col: usize) -> bool {
+        (line, col) >= self.start && (line, col) <= self.end
+    }
+}
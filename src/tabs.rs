@@ -0,0 +1,50 @@
+// Horizontal tab stops of a line, one bit per column
+pub struct TabStops {
+    bits: Vec<u64>,
+    width: usize
+}
+
+const DEFAULT_TAB_STOP: usize = 8;
+
+impl TabStops {
+    pub fn new(width: usize) -> Self {
+        let mut tabs = Self {
+            bits: vec![0; width.div_ceil(64)],
+            width
+        };
+        for col in (DEFAULT_TAB_STOP..width).step_by(DEFAULT_TAB_STOP) {
+            tabs.set(col);
+        }
+        tabs
+    }
+
+    pub fn set(&mut self, col: usize) {
+        if col < self.width {
+            self.bits[col / 64] |= 1 << (col % 64);
+        }
+    }
+
+    pub fn clear(&mut self, col: usize) {
+        if col < self.width {
+            self.bits[col / 64] &= !(1 << (col % 64));
+        }
+    }
+
+    pub fn clear_all(&mut self) {
+        self.bits.iter_mut().for_each(|bits| *bits = 0);
+    }
+
+    pub fn is_set(&self, col: usize) -> bool {
+        col < self.width && self.bits[col / 64] & (1 << (col % 64)) != 0
+    }
+
+    // The next tab stop after a column, or the last column if there's none
+    pub fn next(&self, col: usize) -> usize {
+        (col + 1..self.width).find(|&c| self.is_set(c)).unwrap_or(self.width - 1)
+    }
+
+    // The previous tab stop before a column, or the first column if there's none
+    pub fn previous(&self, col: usize) -> usize {
+        (0..col.min(self.width)).rev().find(|&c| self.is_set(c)).unwrap_or(0)
+    }
+}
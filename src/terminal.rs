@@ -1,18 +1,21 @@
-use std::usize;
-use wgpu::{Backends, BlendState, Buffer, BufferUsages, Color, ColorTargetState, ColorWrites, CommandEncoderDescriptor, Device, DeviceDescriptor, Face, Features, FragmentState, FrontFace, Instance, Limits, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PowerPreference, PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, RequestAdapterOptions, ShaderModuleDescriptor, ShaderSource, Surface, SurfaceConfiguration, SurfaceError, TextureFormat, TextureUsages, TextureViewDescriptor, VertexState, util::{BufferInitDescriptor, DeviceExt, StagingBelt}};
+use std::{collections::HashSet, path::PathBuf, process::Command, time::{Duration, Instant}, usize};
+use wgpu::{Backends, BlendState, Buffer, BufferUsages, ColorTargetState, ColorWrites, CommandEncoderDescriptor, Device, DeviceDescriptor, Face, Features, FragmentState, FrontFace, Instance, Limits, LoadOp, MultisampleState, Operations, PipelineLayoutDescriptor, PolygonMode, PowerPreference, PrimitiveState, PrimitiveTopology, Queue, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, RequestAdapterOptions, ShaderModuleDescriptor, ShaderSource, Surface, SurfaceConfiguration, SurfaceError, TextureFormat, TextureUsages, TextureViewDescriptor, VertexState, util::{BufferInitDescriptor, DeviceExt, StagingBelt}};
 use wgpu_glyph::{GlyphBrush, GlyphBrushBuilder, GlyphCruncher, Section, Text, ab_glyph::{self, Rect}};
-use winit::{dpi::PhysicalSize, window::Window};
+use winit::{dpi::{PhysicalPosition, PhysicalSize}, event::{ModifiersState, MouseButton, MouseScrollDelta}, window::Window};
 
-use crate::{characters::{BACK_CHAR, BELL_CHAR, CR_CHAR, ESC_CHAR, EscapeCode, NEWLINE_CHAR, SPACE_CHAR, TAB_CHAR}, constants::{TERMINAL_COLS, TERMINAL_ROWS, TITLEBAR_MARGIN}, cursor::{Cursor, CursorDirection}, screen::ScreenBuffer};
+use crate::{attributes::{Attributes, Color}, charset::{Charset, Charsets}, clipboard::{Clipboard, ClipboardPolicy, Selection, decode_base64, encode_base64}, characters::{BACK_CHAR, CR_CHAR, ENQ_CHAR, FF_CHAR, NEWLINE_CHAR, SI_CHAR, SO_CHAR, TAB_CHAR, VT_CHAR}, constants::{ANSWERBACK, CLIPBOARD_POLICY, TERMINAL_NAME, TERMINAL_COLS, TERMINAL_ROWS, TITLEBAR_MARGIN}, cursor::{Cursor, CursorDirection}, hyperlink::{Hyperlink, Hyperlinks}, modes::{MOUSE_ENCODING_MODES, MOUSE_TRACKING_MODES, Mode, Modes}, mouse::{Button, MouseEvent, MouseEventKind}, palette::{DEFAULT_BACKGROUND, DEFAULT_CURSOR, DEFAULT_FOREGROUND, Palette, format_color, parse_color}, parser::{Action, Params, Parser}, screen::{Cell, Mark, ScreenBuffer, char_width}, selection::TextSelection, tabs::TabStops};
 
 // REF: https://www.vt100.net/docs/la100-rm/chapter2.html
 
-const TAB_STOP: usize = 8;
+const TITLE_STACK_SIZE: usize = 10;
 const FONT_SIZE: f32 = 20.0;
 const CUR_CHAR: &str = "█";
-const CUR_COLOR: [f32; 4] = [1.0, 0.0, 0.0, 0.5];
-const CHR_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-const CELL_COLOR: [f32; 3] = [0.015, 0.015, 0.015];
+const CUR_ALPHA: f32 = 0.5;
+const ZWJ_CHAR: char = '\u{200d}';
+// How long a synchronized update can hold the display
+const SYNC_TIMEOUT: Duration = Duration::from_secs(1);
+const PASTE_START: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -42,6 +45,16 @@ impl Vertex {
     }
 }
 
+// The state saved by DECSC, the column past the last one is kept as is so
+// that a pending wrap is restored too
+#[derive(Clone, Copy)]
+struct SavedCursor {
+    cursor: Cursor,
+    attributes: Attributes,
+    charsets: Charsets,
+    origin: bool
+}
+
 pub struct Terminal {
     pub surface: Surface,
     pub device: Device,
@@ -51,11 +64,40 @@ pub struct Terminal {
     pub glyph_brush: GlyphBrush<()>,
     pub staging_belt: StagingBelt,
     pub buffer: ScreenBuffer,
+    pub alt_buffer: ScreenBuffer,
+    pub alt_screen: bool,
     pub scale_factor: f32,
     pub cursor: Cursor,
+    pub attributes: Attributes,
+    pub charsets: Charsets,
+    pub scroll_top: usize,
+    pub scroll_bottom: usize,
+    pub tabs: TabStops,
+    pub modes: Modes,
+    pub palette: Palette,
+    pub clipboard: Clipboard,
+    pub hyperlinks: Hyperlinks,
+    hyperlink: Option<u32>,
+    focused: bool,
+    sync_started: Option<Instant>,
+    mouse_position: PhysicalPosition<f64>,
+    mouse_cell: Option<(usize, usize)>,
+    mouse_button: Option<Button>,
+    selection: Option<TextSelection>,
+    // Where the selection made with the mouse started, as (line, column)
+    selection_start: Option<(usize, usize)>,
+    pub title: String,
+    pub cwd: Option<PathBuf>,
+    hostname: Option<String>,
+    pub icon_name: String,
+    title_stack: Vec<(String, String)>,
+    title_changed: bool,
+    // Saved cursors of the main and alternate screens
+    saved_cursors: [Option<SavedCursor>; 2],
+    responses: Vec<u8>,
     pub cell_size: Rect,
     pub quad_pipeline: RenderPipeline,
-    start_line: usize
+    parser: Parser
 }
 
 impl Terminal {
@@ -136,11 +178,38 @@ impl Terminal {
         Self {
             surface, device, queue, config, size, glyph_brush, staging_belt,
             scale_factor: window.scale_factor() as f32,
-            buffer: ScreenBuffer::new(TERMINAL_COLS as usize, TERMINAL_ROWS as usize),
+            buffer: ScreenBuffer::new(TERMINAL_COLS as usize, TERMINAL_ROWS as usize, true),
+            alt_buffer: ScreenBuffer::new(TERMINAL_COLS as usize, TERMINAL_ROWS as usize, false),
+            alt_screen: false,
             cell_size: bounds,
             cursor: Cursor::new(),
+            attributes: Attributes::default(),
+            charsets: Charsets::new(),
+            scroll_top: 0,
+            scroll_bottom: TERMINAL_ROWS as usize - 1,
+            tabs: TabStops::new(TERMINAL_COLS as usize),
+            modes: Modes::new(),
+            palette: Palette::new(),
+            clipboard: Clipboard::new(CLIPBOARD_POLICY),
+            hyperlinks: Hyperlinks::new(),
+            hyperlink: None,
+            focused: true,
+            sync_started: None,
+            mouse_position: PhysicalPosition::new(0.0, 0.0),
+            mouse_cell: None,
+            mouse_button: None,
+            selection: None,
+            selection_start: None,
+            title: TERMINAL_NAME.to_string(),
+            cwd: None,
+            hostname: local_hostname(),
+            icon_name: TERMINAL_NAME.to_string(),
+            title_stack: vec![],
+            title_changed: false,
+            saved_cursors: [None; 2],
+            responses: vec![],
             quad_pipeline,
-            start_line: 0
+            parser: Parser::new()
         }
     }
 
@@ -156,58 +225,932 @@ impl Terminal {
         }
     }
 
-    pub fn set_data(&mut self, buf: &mut Vec<u8>) {
-        let mut i = 0;
-        while i < buf.len() {
-            let b = buf[i] as char;
-            if b == BACK_CHAR {
-                self.cursor.move_to(CursorDirection::Left);
-            } else if b == ESC_CHAR {
-                let (remain, param, inter, final_byte) = EscapeCode::parse_csi(&buf[i+1..]);
-                // Process code here
-                let param = param.iter().map(|c| *c as char).collect::<String>();
-                let inter = inter.iter().map(|c| *c as char).collect::<String>();
-                let final_byte = final_byte as char;
-                match (param.as_str(), inter.as_str(), final_byte) {
-                    ("", "", 'J') | ("0", "", 'J') | ("1", "", 'J') | ("2", "", 'J') => {
-                        // Just clear everything for now
-                        self.buffer.clear();
-                        self.start_line = 0;
-                    },
-                    ("", "", 'K') => {
-                        self.buffer.set_char_at(0, self.cursor.row, self.cursor.col);
-                    },
-                    ("", "", 'H') => {
-                        self.cursor.move_to(CursorDirection::BOF);
-                    },
-                    _ => println!("Unhandled CSI sequence: [{}{}{}", param, inter, final_byte)
+    pub fn set_data(&mut self, buf: &[u8]) {
+        for action in self.parser.parse(buf) {
+            match action {
+                Action::Print(c) => self.print(c),
+                Action::Execute(byte) => self.execute(byte as char),
+                Action::CsiDispatch { params, intermediates, ignore: false, action } => {
+                    self.csi_dispatch(&params, &intermediates, action);
+                },
+                Action::EscDispatch { intermediates, ignore: false, byte } => {
+                    self.esc_dispatch(&intermediates, byte);
+                },
+                Action::OscDispatch { params, bell_terminated } => self.osc_dispatch(&params, bell_terminated),
+                Action::Hook { params, intermediates, ignore: false, action } => {
+                    self.dcs_hook(&params, &intermediates, action);
+                },
+                _ => {}
+            }
+        }
+    }
+
+    // Bytes to be written back to the shell, like replies to the mode queries
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
+
+    // The new window title, if it was changed since the last call
+    pub fn take_title_change(&mut self) -> Option<String> {
+        if self.title_changed {
+            self.title_changed = false;
+            Some(self.title.clone())
+        } else {
+            None
+        }
+    }
+
+    fn set_title(&mut self, title: String) {
+        self.title = title;
+        self.title_changed = true;
+    }
+
+    fn respond(&mut self, data: &str) {
+        self.responses.extend_from_slice(data.as_bytes());
+    }
+
+    fn print(&mut self, c: char) {
+        let c = self.charsets.translate(c);
+        let width = char_width(c);
+        // Combining marks and characters after a zero width joiner are part of
+        // the previous character
+        if width == 0 || self.joins_previous() {
+            if let Some((row, col)) = self.previous_cell() {
+                self.buffer.append_to_cell(row, col, c);
+            }
+            return;
+        }
+        let cols = TERMINAL_COLS as usize;
+        if self.cursor.col + width > cols {
+            if self.modes.get(Mode::Autowrap) {
+                self.cursor.move_to(CursorDirection::BOL);
+                self.linefeed();
+            } else {
+                self.cursor.set_position(self.cursor.row, cols - width);
+            }
+        }
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let blank = Cell::blank(&self.attributes);
+        if self.modes.get(Mode::Insert) {
+            self.buffer.insert_cells(row, col, width, blank);
+        }
+        self.buffer.split_wide_char(row, col, blank);
+        self.buffer.split_wide_char(row, col + width, blank);
+        let cell = Cell { c, attrs: self.attributes, link: self.hyperlink, width: width as u8, combining: None };
+        self.buffer.set_cell_at(cell, row, col);
+        if width == 2 {
+            self.buffer.set_cell_at(Cell { c: '\0', width: 0, ..cell }, row, col + 1);
+        }
+        for _ in 0..width {
+            self.cursor.move_to(CursorDirection::Right);
+        }
+    }
+
+    // The cell before the cursor, a wide character is found from its spacer
+    fn previous_cell(&self) -> Option<(usize, usize)> {
+        let col = self.cursor.col.checked_sub(1)?;
+        if col > 0 && self.buffer.get_cell_at(self.cursor.row, col).width == 0 {
+            Some((self.cursor.row, col - 1))
+        } else {
+            Some((self.cursor.row, col))
+        }
+    }
+
+    fn joins_previous(&self) -> bool {
+        matches!(self.previous_cell(), Some((row, col))
+            if self.buffer.grapheme(&self.buffer.get_cell_at(row, col)).ends_with(ZWJ_CHAR))
+    }
+
+    fn execute(&mut self, c: char) {
+        if c == BACK_CHAR {
+            self.cursor.move_to(CursorDirection::Left);
+        } else if c == NEWLINE_CHAR || c == VT_CHAR || c == FF_CHAR {
+            self.linefeed();
+            if self.modes.get(Mode::LinefeedNewline) {
+                self.cursor.move_to(CursorDirection::BOL);
+            }
+        } else if c == SO_CHAR {
+            self.charsets.locking_shift(1);
+        } else if c == SI_CHAR {
+            self.charsets.locking_shift(0);
+        } else if c == ENQ_CHAR {
+            self.respond(ANSWERBACK);
+        } else if c == CR_CHAR {
+            self.cursor.move_to(CursorDirection::BOL);
+        } else if c == TAB_CHAR {
+            self.tab_forward(1);
+        }
+    }
+
+    fn tab_forward(&mut self, count: u16) {
+        let mut col = self.cursor.col.min(TERMINAL_COLS as usize - 1);
+        for _ in 0..count {
+            col = self.tabs.next(col);
+        }
+        self.cursor.set_position(self.cursor.row, col);
+    }
+
+    fn tab_backward(&mut self, count: u16) {
+        let mut col = self.cursor.col.min(TERMINAL_COLS as usize - 1);
+        for _ in 0..count {
+            col = self.tabs.previous(col);
+        }
+        self.cursor.set_position(self.cursor.row, col);
+    }
+
+    fn linefeed(&mut self) {
+        if self.cursor.row == self.scroll_bottom {
+            self.buffer.scroll_up(self.scroll_top, self.scroll_bottom, 1, Cell::blank(&self.attributes));
+        } else {
+            self.cursor.move_to(CursorDirection::Down);
+        }
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cursor.row == self.scroll_top {
+            self.buffer.scroll_down(self.scroll_top, self.scroll_bottom, 1, Cell::blank(&self.attributes));
+        } else {
+            self.cursor.move_to(CursorDirection::Up);
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[u8], byte: u8) {
+        match (intermediates, byte) {
+            // ST, the end of an OSC or DCS string that was already dispatched
+            ([], b'\\') => {},
+            ([], b'7') => self.save_cursor(),
+            ([], b'8') => self.restore_cursor(),
+            ([], b'=') => self.set_mode(Mode::KeypadApplication, true),
+            ([], b'>') => self.set_mode(Mode::KeypadApplication, false),
+            ([], b'D') => self.linefeed(),
+            ([], b'H') => self.tabs.set(self.cursor.col.min(TERMINAL_COLS as usize - 1)),
+            ([], b'N') => self.charsets.single_shift(2),
+            ([], b'O') => self.charsets.single_shift(3),
+            ([], b'n') => self.charsets.locking_shift(2),
+            ([], b'o') => self.charsets.locking_shift(3),
+            ([slot @ b'('..=b'+'], designator) => match Charset::from_designator(designator) {
+                Some(charset) => self.charsets.designate((slot - b'(') as usize, charset),
+                None => println!("Unhandled charset: {}", designator as char)
+            },
+            ([], b'M') => self.reverse_index(),
+            ([], b'E') => {
+                self.cursor.move_to(CursorDirection::BOL);
+                self.linefeed();
+            },
+            _ => println!("Unhandled ESC sequence: {:?} {}", intermediates, byte as char)
+        }
+    }
+
+    fn dcs_hook(&mut self, params: &Params, intermediates: &[u8], action: char) {
+        match (intermediates, action) {
+            // The older form of synchronized updates, BSU and ESU
+            ([b'='], 's') => match params.get(0, 0) {
+                1 => self.set_mode(Mode::SynchronizedOutput, true),
+                2 => self.set_mode(Mode::SynchronizedOutput, false),
+                _ => {}
+            },
+            _ => println!("Unhandled DCS sequence: {:?} {}", intermediates, action)
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[u8], action: char) {
+        match (intermediates, action) {
+            ([], 'A') => self.cursor.move_by(-(params.get(0, 1) as isize), 0),
+            ([], 'B') | ([], 'e') => self.cursor.move_by(params.get(0, 1) as isize, 0),
+            ([], 'C') | ([], 'a') => self.cursor.move_by(0, params.get(0, 1) as isize),
+            ([], 'D') => self.cursor.move_by(0, -(params.get(0, 1) as isize)),
+            ([], 'E') => {
+                self.cursor.move_by(params.get(0, 1) as isize, 0);
+                self.cursor.move_to(CursorDirection::BOL);
+            },
+            ([], 'F') => {
+                self.cursor.move_by(-(params.get(0, 1) as isize), 0);
+                self.cursor.move_to(CursorDirection::BOL);
+            },
+            ([], 'G') | ([], '`') => self.cursor.set_position(self.cursor.row, params.get(0, 1) as usize - 1),
+            ([], 'd') => {
+                let col = self.cursor.col;
+                self.move_cursor_to(params.get(0, 1) as usize - 1, col);
+            },
+            ([], 'H') | ([], 'f') => {
+                self.move_cursor_to(params.get(0, 1) as usize - 1, params.get(1, 1) as usize - 1);
+            },
+            ([], 'I') => self.tab_forward(params.get(0, 1)),
+            ([], 'Z') => self.tab_backward(params.get(0, 1)),
+            ([], 'g') => match params.get(0, 0) {
+                0 => self.tabs.clear(self.cursor.col.min(TERMINAL_COLS as usize - 1)),
+                3 => self.tabs.clear_all(),
+                _ => {}
+            },
+            ([], 'J') => self.erase_in_display(params.get(0, 0)),
+            ([], 'K') => self.erase_in_line(params.get(0, 0)),
+            ([], 'X') => {
+                let col = self.cursor.col.min(TERMINAL_COLS as usize - 1);
+                self.buffer.erase(self.cursor.row, col, col + params.get(0, 1) as usize, Cell::blank(&self.attributes));
+            },
+            ([], '@') => {
+                let col = self.cursor.col.min(TERMINAL_COLS as usize - 1);
+                self.buffer.insert_cells(self.cursor.row, col, params.get(0, 1) as usize, Cell::blank(&self.attributes));
+            },
+            ([], 'P') => {
+                let col = self.cursor.col.min(TERMINAL_COLS as usize - 1);
+                self.buffer.delete_cells(self.cursor.row, col, params.get(0, 1) as usize, Cell::blank(&self.attributes));
+            },
+            ([], 'L') if self.in_scroll_region() => {
+                self.buffer.insert_lines(self.cursor.row, self.scroll_bottom, params.get(0, 1) as usize, Cell::blank(&self.attributes));
+                self.cursor.move_to(CursorDirection::BOL);
+            },
+            ([], 'M') if self.in_scroll_region() => {
+                self.buffer.delete_lines(self.cursor.row, self.scroll_bottom, params.get(0, 1) as usize, Cell::blank(&self.attributes));
+                self.cursor.move_to(CursorDirection::BOL);
+            },
+            ([], 'L') | ([], 'M') => {},
+            ([], 'S') => {
+                self.buffer.scroll_up(self.scroll_top, self.scroll_bottom, params.get(0, 1) as usize, Cell::blank(&self.attributes));
+            },
+            ([], 'T') => {
+                self.buffer.scroll_down(self.scroll_top, self.scroll_bottom, params.get(0, 1) as usize, Cell::blank(&self.attributes));
+            },
+            ([], 'r') => {
+                let top = params.get(0, 1) as usize - 1;
+                let bottom = (params.get(1, TERMINAL_ROWS as u16) as usize).min(TERMINAL_ROWS as usize) - 1;
+                if top < bottom {
+                    self.scroll_top = top;
+                    self.scroll_bottom = bottom;
+                    self.move_cursor_to(0, 0);
                 }
-                let parsed_size = buf.len() - remain.len() - 1;
-                if parsed_size > 0 {
-                    i += parsed_size;
-                } else {
-                    i += 1;
+            },
+            ([], 'm') => self.set_graphic_rendition(params),
+            ([], 'h') | ([], 'l') | ([b'?'], 'h') | ([b'?'], 'l') => {
+                for param in params.iter() {
+                    match Self::mode_from(intermediates, param[0]) {
+                        Some(mode) => self.set_mode(mode, action == 'h'),
+                        None => println!("Unhandled mode: {:?} {} {}", intermediates, param[0], action)
+                    }
                 }
-            } else if b != BELL_CHAR {
-                if b == NEWLINE_CHAR {
-                    self.cursor.move_to(CursorDirection::NextLine);
-                } else if b == CR_CHAR {
-                    self.cursor.move_to(CursorDirection::BOL);
-                } else if b == TAB_CHAR {
-                    let next = (1 + self.cursor.col / TAB_STOP) * TAB_STOP;
-                    for _ in 0..(next - self.cursor.col) {
-                        self.buffer.set_char_at(SPACE_CHAR as u8, self.cursor.row, self.cursor.col);
-                        self.cursor.move_to(CursorDirection::Right);
+            },
+            ([], 's') => self.save_cursor(),
+            ([], 'u') => self.restore_cursor(),
+            ([b'?'], 's') => {
+                for mode in params.iter().filter_map(|param| Mode::from_private(param[0])) {
+                    self.modes.save(mode);
+                }
+            },
+            ([b'?'], 'r') => {
+                for mode in params.iter().filter_map(|param| Mode::from_private(param[0])) {
+                    if let Some(enabled) = self.modes.saved(mode) {
+                        self.set_mode(mode, enabled);
                     }
-                } else {
-                    self.buffer.set_char_at(b as u8, self.cursor.row, self.cursor.col);
-                    self.cursor.move_to(CursorDirection::Right);
                 }
-                if self.cursor.row >= self.start_line + TERMINAL_ROWS as usize {
-                    self.start_line = 1 + self.cursor.row - TERMINAL_ROWS as usize;
+            },
+            ([b'$'], 'p') | ([b'?', b'$'], 'p') => {
+                // DECRQM, reply with DECRPM: CSI ? Ps ; Pm $ y
+                let mode = params.get(0, 0);
+                let value = self.modes.report(Self::mode_from(&intermediates[..intermediates.len() - 1], mode));
+                let private = if intermediates[0] == b'?' { "?" } else { "" };
+                self.respond(&format!("\x1b[{}{};{}$y", private, mode, value));
+            },
+            ([], 'n') | ([b'?'], 'n') => self.device_status_report(params.get(0, 0), !intermediates.is_empty()),
+            // Primary DA, identify as a VT220 with ANSI colors
+            ([], 'c') if params.get(0, 0) == 0 => self.respond("\x1b[?62;22c"),
+            // Secondary DA, the firmware version is the crate version as MMmmpp
+            ([b'>'], 'c') if params.get(0, 0) == 0 => {
+                let version = Self::version_number();
+                self.respond(&format!("\x1b[>1;{};0c", version));
+            },
+            // Tertiary DA, the unit id is just zeros
+            ([b'='], 'c') if params.get(0, 0) == 0 => self.respond("\x1bP!|00000000\x1b\\"),
+            // XTVERSION
+            ([b'>'], 'q') if params.get(0, 0) == 0 => {
+                self.respond(&format!("\x1bP>|{}({})\x1b\\", TERMINAL_NAME, env!("CARGO_PKG_VERSION")));
+            },
+            ([], 't') => self.window_manipulation(params),
+            _ => println!("Unhandled CSI sequence: {:?} {:?} {}", params, intermediates, action)
+        }
+    }
+
+    // REF: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Operating-System-Commands
+    fn osc_dispatch(&mut self, params: &[Vec<u8>], bell_terminated: bool) {
+        let command = String::from_utf8_lossy(&params[0]).to_string();
+        // The text itself could contain `;`, so join the rest of the params back
+        let text = String::from_utf8_lossy(&params[1..].join(&b';')).to_string();
+        match command.as_str() {
+            "0" => {
+                self.icon_name = text.clone();
+                self.set_title(text);
+            },
+            "1" => self.icon_name = text,
+            "2" => self.set_title(text),
+            "4" => self.set_palette_colors(&params[1..], bell_terminated),
+            "10" | "11" | "12" => self.set_dynamic_colors(command.parse().unwrap(), &params[1..], bell_terminated),
+            "104" if params.len() < 2 => self.palette.reset_colors(),
+            "104" => {
+                for index in params[1..].iter().filter_map(|p| std::str::from_utf8(p).ok()?.parse::<u8>().ok()) {
+                    self.palette.reset_color(index);
+                }
+            },
+            "7" => self.set_working_directory(&text),
+            "8" => self.set_hyperlink(&params[1..]),
+            "52" => self.clipboard_operation(&params[1..], bell_terminated),
+            "133" => self.shell_integration_mark(&params[1..]),
+            "110" => self.palette.foreground = DEFAULT_FOREGROUND,
+            "111" => self.palette.background = DEFAULT_BACKGROUND,
+            "112" => self.palette.cursor = DEFAULT_CURSOR,
+            _ => println!("Unhandled OSC sequence: {}", command)
+        }
+    }
+
+    // OSC 4 ; index ; spec [; index ; spec ...], a `?` spec is a query
+    fn set_palette_colors(&mut self, params: &[Vec<u8>], bell_terminated: bool) {
+        for pair in params.chunks(2) {
+            if let [index, spec] = pair {
+                let index = match std::str::from_utf8(index).ok().and_then(|i| i.parse::<u8>().ok()) {
+                    Some(index) => index,
+                    None => continue
+                };
+                if spec.as_slice() == b"?" {
+                    let color = format_color(self.palette.color(index));
+                    self.respond_osc(&format!("4;{};{}", index, color), bell_terminated);
+                } else if let Some(color) = std::str::from_utf8(spec).ok().and_then(parse_color) {
+                    self.palette.set_color(index, color);
                 }
             }
-            i += 1;
+        }
+    }
+
+    // OSC 10/11/12 set the foreground, background and cursor colors, extra
+    // params continue with the next color, e.g. `OSC 10 ; fg ; bg`
+    fn set_dynamic_colors(&mut self, first: u16, params: &[Vec<u8>], bell_terminated: bool) {
+        for (command, spec) in (first..=12).zip(params) {
+            let color = match command {
+                10 => &mut self.palette.foreground,
+                11 => &mut self.palette.background,
+                _ => &mut self.palette.cursor
+            };
+            if spec.as_slice() == b"?" {
+                let color = format_color(*color);
+                self.respond_osc(&format!("{};{}", command, color), bell_terminated);
+            } else if let Some(value) = std::str::from_utf8(spec).ok().and_then(parse_color) {
+                *color = value;
+            }
+        }
+    }
+
+    // OSC 7 ; file://host/path, the path is percent-encoded. A directory on
+    // another host, like from a shell over ssh, is forgotten.
+    fn set_working_directory(&mut self, uri: &str) {
+        let location = uri.strip_prefix("file://")
+            .and_then(|rest| rest.find('/').map(|index| (&rest[..index], &rest[index..])));
+        match location {
+            Some((host, path)) if self.is_local_host(host) => self.cwd = Some(PathBuf::from(percent_decode(path))),
+            Some(_) => self.cwd = None,
+            None => println!("Unhandled working directory: {}", uri)
+        }
+    }
+
+    fn is_local_host(&self, host: &str) -> bool {
+        host.is_empty() || host == "localhost"
+            || matches!(&self.hostname, Some(hostname) if same_host(hostname, host))
+    }
+
+    // Free the hyperlinks of the lines that are gone
+    fn prune_hyperlinks(&mut self) {
+        let used: HashSet<u32> = self.buffer.links()
+            .chain(self.alt_buffer.links())
+            .chain(self.hyperlink)
+            .collect();
+        self.hyperlinks.retain(&used);
+    }
+
+    // OSC 8 ; params ; URI, where params are `key=value` pairs separated by `:`,
+    // an empty URI closes the link
+    fn set_hyperlink(&mut self, params: &[Vec<u8>]) {
+        if params.len() < 2 {
+            return;
+        }
+        let uri = String::from_utf8_lossy(&params[1..].join(&b';')).to_string();
+        if uri.is_empty() {
+            self.hyperlink = None;
+            return;
+        }
+        let id = String::from_utf8_lossy(&params[0]).split(':')
+            .find_map(|param| param.strip_prefix("id=").map(|id| id.to_string()));
+        self.hyperlink = Some(self.hyperlinks.intern(Hyperlink { id, uri }));
+    }
+
+    // OSC 133 ; A/B/C/D, the command end mark can carry the exit code: `D ; code`
+    fn shell_integration_mark(&mut self, params: &[Vec<u8>]) {
+        let mark = match params.first().map(|p| p.as_slice()) {
+            Some(b"A") => Mark::PromptStart,
+            Some(b"B") => Mark::CommandStart,
+            Some(b"C") => Mark::OutputStart,
+            Some(b"D") => {
+                let exit_code = params.get(1).and_then(|code| std::str::from_utf8(code).ok()?.parse().ok());
+                Mark::CommandEnd(exit_code)
+            },
+            _ => return
+        };
+        let col = self.cursor.col.min(TERMINAL_COLS as usize - 1);
+        self.buffer.add_mark(self.cursor.row, col, mark);
+    }
+
+    // OSC 52 ; targets ; data, the data is base64 encoded text to be copied, or
+    // `?` to read the clipboard back. `p` targets the primary selection and the
+    // other targets (`c`, `s` or cut buffers) all go to the clipboard.
+    fn clipboard_operation(&mut self, params: &[Vec<u8>], bell_terminated: bool) {
+        let (targets, data) = match params {
+            [targets, data, ..] => (targets, data),
+            _ => return
+        };
+        let mut selections = vec![];
+        for &target in targets.iter() {
+            let selection = if target == b'p' { Selection::Primary } else { Selection::Clipboard };
+            if !selections.contains(&selection) {
+                selections.push(selection);
+            }
+        }
+        if selections.is_empty() {
+            selections.push(Selection::Clipboard);
+        }
+
+        if data.as_slice() == b"?" {
+            if self.clipboard.policy != ClipboardPolicy::AllowReadWrite {
+                return;
+            }
+            let target = if selections[0] == Selection::Primary { "p" } else { "c" };
+            let text = self.clipboard.load(selections[0]).unwrap_or_default();
+            self.respond_osc(&format!("52;{};{}", target, encode_base64(text.as_bytes())), bell_terminated);
+        } else if self.clipboard.policy != ClipboardPolicy::Deny {
+            let text = match decode_base64(data).and_then(|bytes| String::from_utf8(bytes).ok()) {
+                Some(text) => text,
+                None => return
+            };
+            for selection in selections {
+                self.clipboard.store(selection, &text);
+            }
+        }
+    }
+
+    // Reply with the same terminator as the query
+    fn respond_osc(&mut self, data: &str, bell_terminated: bool) {
+        let terminator = if bell_terminated { "\x07" } else { "\x1b\\" };
+        self.respond(&format!("\x1b]{}{}", data, terminator));
+    }
+
+    // XTWINOPS, only the title stack operations are supported
+    fn window_manipulation(&mut self, params: &Params) {
+        match (params.get(0, 0), params.get(1, 0)) {
+            (22, _) => {
+                if self.title_stack.len() >= TITLE_STACK_SIZE {
+                    self.title_stack.remove(0);
+                }
+                self.title_stack.push((self.title.clone(), self.icon_name.clone()));
+            },
+            (23, which) => {
+                if let Some((title, icon_name)) = self.title_stack.pop() {
+                    if which == 0 || which == 1 {
+                        self.icon_name = icon_name;
+                    }
+                    if which == 0 || which == 2 {
+                        self.set_title(title);
+                    }
+                }
+            },
+            (op, _) => println!("Unhandled window manipulation: {}", op)
+        }
+    }
+
+    fn device_status_report(&mut self, report: u16, private: bool) {
+        match report {
+            5 => self.respond("\x1b[0n"),
+            6 => {
+                let mut row = self.cursor.row;
+                if self.modes.get(Mode::Origin) {
+                    row = row.saturating_sub(self.scroll_top);
+                }
+                let col = self.cursor.col.min(TERMINAL_COLS as usize - 1);
+                let private = if private { "?" } else { "" };
+                self.respond(&format!("\x1b[{}{};{}R", private, row + 1, col + 1));
+            },
+            _ => println!("Unhandled DSR: {}", report)
+        }
+    }
+
+    fn version_number() -> u32 {
+        env!("CARGO_PKG_VERSION").split('.')
+            .take(3)
+            .fold(0, |version, part| version * 100 + part.parse::<u32>().unwrap_or(0))
+    }
+
+    fn mode_from(intermediates: &[u8], mode: u16) -> Option<Mode> {
+        match intermediates {
+            [] => Mode::from_ansi(mode),
+            [b'?'] => Mode::from_private(mode),
+            _ => None
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode, enabled: bool) {
+        for group in [MOUSE_TRACKING_MODES, MOUSE_ENCODING_MODES] {
+            if enabled && group.contains(&mode) {
+                for other in group {
+                    self.modes.set(other, false);
+                }
+            }
+        }
+        match mode {
+            Mode::AltScreen | Mode::AltScreenClear | Mode::AltScreenSaveCursor => self.switch_screen(mode, enabled),
+            _ => {}
+        }
+        self.modes.set(mode, enabled);
+        if mode == Mode::SynchronizedOutput {
+            self.sync_started = if enabled { Some(Instant::now()) } else { None };
+        }
+        if mode == Mode::Origin {
+            self.move_cursor_to(0, 0);
+        }
+    }
+
+    // Move to a position on the screen, in origin mode the rows are relative to
+    // the top margin and the cursor can't go outside of the scroll region
+    fn move_cursor_to(&mut self, row: usize, col: usize) {
+        if self.modes.get(Mode::Origin) {
+            self.cursor.set_position((row + self.scroll_top).min(self.scroll_bottom), col);
+        } else {
+            self.cursor.set_position(row, col);
+        }
+    }
+
+    // The alternate screen is swapped in place of the main buffer, so that
+    // everything else only has to deal with `self.buffer`
+    fn switch_screen(&mut self, mode: Mode, alt_screen: bool) {
+        if alt_screen == self.alt_screen {
+            return;
+        }
+        if alt_screen {
+            if mode == Mode::AltScreenSaveCursor {
+                self.save_cursor();
+                self.alt_buffer = ScreenBuffer::new(TERMINAL_COLS as usize, TERMINAL_ROWS as usize, false);
+                self.prune_hyperlinks();
+            }
+        } else if mode == Mode::AltScreenClear || mode == Mode::AltScreenSaveCursor {
+            self.buffer = ScreenBuffer::new(TERMINAL_COLS as usize, TERMINAL_ROWS as usize, false);
+            self.prune_hyperlinks();
+        }
+        std::mem::swap(&mut self.buffer, &mut self.alt_buffer);
+        self.alt_screen = alt_screen;
+        if !alt_screen && mode == Mode::AltScreenSaveCursor {
+            self.restore_cursor();
+        }
+    }
+
+    fn save_cursor(&mut self) {
+        self.saved_cursors[self.alt_screen as usize] = Some(SavedCursor {
+            cursor: self.cursor,
+            attributes: self.attributes,
+            charsets: self.charsets,
+            origin: self.modes.get(Mode::Origin)
+        });
+    }
+
+    // Without a saved cursor, the cursor goes home with the default state
+    fn restore_cursor(&mut self) {
+        let saved = self.saved_cursors[self.alt_screen as usize].unwrap_or(SavedCursor {
+            cursor: Cursor::new(),
+            attributes: Attributes::default(),
+            charsets: Charsets::new(),
+            origin: false
+        });
+        self.cursor = saved.cursor;
+        self.attributes = saved.attributes;
+        self.charsets = saved.charsets;
+        self.modes.set(Mode::Origin, saved.origin);
+    }
+
+    fn in_scroll_region(&self) -> bool {
+        self.cursor.row >= self.scroll_top && self.cursor.row <= self.scroll_bottom
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let blank = Cell::blank(&self.attributes);
+        let (rows, cols) = (TERMINAL_ROWS as usize, TERMINAL_COLS as usize);
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                for row in self.cursor.row + 1..rows {
+                    self.buffer.erase(row, 0, cols, blank);
+                }
+            },
+            1 => {
+                for row in 0..self.cursor.row {
+                    self.buffer.erase(row, 0, cols, blank);
+                }
+                self.erase_in_line(1);
+            },
+            2 => {
+                for row in 0..rows {
+                    self.buffer.erase(row, 0, cols, blank);
+                }
+            },
+            3 => {
+                self.buffer.clear_history();
+                self.prune_hyperlinks();
+            },
+            _ => {}
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let blank = Cell::blank(&self.attributes);
+        let col = self.cursor.col.min(TERMINAL_COLS as usize - 1);
+        match mode {
+            0 => self.buffer.erase(self.cursor.row, col, TERMINAL_COLS as usize, blank),
+            1 => self.buffer.erase(self.cursor.row, 0, col + 1, blank),
+            2 => self.buffer.erase(self.cursor.row, 0, TERMINAL_COLS as usize, blank),
+            _ => {}
+        }
+    }
+
+    fn set_graphic_rendition(&mut self, params: &Params) {
+        if params.is_empty() {
+            self.attributes = Attributes::default();
+            return;
+        }
+
+        let mut params = params.iter();
+        while let Some(param) = params.next() {
+            match param[0] {
+                0 => self.attributes = Attributes::default(),
+                1 => self.attributes.bold = true,
+                2 => self.attributes.faint = true,
+                3 => self.attributes.italic = true,
+                // 4:0 turns the underline off, other styles like 4:3 (curly) are drawn as underline
+                4 => self.attributes.underline = param.get(1) != Some(&0),
+                5 | 6 => self.attributes.blink = true,
+                7 => self.attributes.inverse = true,
+                8 => self.attributes.hidden = true,
+                9 => self.attributes.strikethrough = true,
+                21 => self.attributes.underline = true,
+                22 => {
+                    self.attributes.bold = false;
+                    self.attributes.faint = false;
+                },
+                23 => self.attributes.italic = false,
+                24 => self.attributes.underline = false,
+                25 => self.attributes.blink = false,
+                27 => self.attributes.inverse = false,
+                28 => self.attributes.hidden = false,
+                29 => self.attributes.strikethrough = false,
+                n @ 30..=37 => self.attributes.fg = Color::Indexed((n - 30) as u8),
+                38 => self.attributes.fg = Self::parse_extended_color(param, &mut params).unwrap_or(self.attributes.fg),
+                39 => self.attributes.fg = Color::Default,
+                n @ 40..=47 => self.attributes.bg = Color::Indexed((n - 40) as u8),
+                48 => self.attributes.bg = Self::parse_extended_color(param, &mut params).unwrap_or(self.attributes.bg),
+                49 => self.attributes.bg = Color::Default,
+                n @ 90..=97 => self.attributes.fg = Color::Indexed((n - 90 + 8) as u8),
+                n @ 100..=107 => self.attributes.bg = Color::Indexed((n - 100 + 8) as u8),
+                n => println!("Unhandled SGR parameter: {}", n)
+            }
+        }
+    }
+
+    // Parse the color of SGR 38/48, either from its colon separated sub-parameters
+    // (`38:5:n`, `38:2:r:g:b` or `38:2:cs:r:g:b`) or from the parameters that follow
+    // it (`38;5;n`, `38;2;r;g;b`).
+    fn parse_extended_color<'a>(param: &[u16], rest: &mut impl Iterator<Item = &'a Vec<u16>>) -> Option<Color> {
+        let values: Vec<u16> = if param.len() > 1 {
+            match param[1] {
+                2 if param.len() >= 6 => vec![2, param[3], param[4], param[5]],
+                _ => param[1..].to_vec()
+            }
+        } else {
+            let kind = rest.next()?[0];
+            let count = if kind == 2 { 3 } else { 1 };
+            let mut values = vec![kind];
+            for _ in 0..count {
+                values.push(rest.next()?[0]);
+            }
+            values
+        };
+
+        match values.as_slice() {
+            [5, n, ..] => Some(Color::Indexed(*n as u8)),
+            [2, r, g, b, ..] => Some(Color::Rgb(*r as u8, *g as u8, *b as u8)),
+            _ => None
+        }
+    }
+
+    // Convert a position in the window into a cell on the screen
+    pub fn cell_at(&self, position: PhysicalPosition<f64>) -> Option<(usize, usize)> {
+        let cell_width = (self.cell_size.width() * self.scale_factor) as f64;
+        let cell_height = (self.cell_size.height() * self.scale_factor) as f64;
+        let y = position.y - (TITLEBAR_MARGIN * self.scale_factor) as f64;
+        if position.x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (row, col) = ((y / cell_height) as usize, (position.x / cell_width) as usize);
+        if row < TERMINAL_ROWS as usize && col < TERMINAL_COLS as usize {
+            Some((row, col))
+        } else {
+            None
+        }
+    }
+
+    // Tell the application when the window gains or loses focus, if it asked to
+    pub fn set_focus(&mut self, focused: bool) -> Option<Vec<u8>> {
+        self.focused = focused;
+        if !self.modes.get(Mode::FocusReporting) {
+            return None;
+        }
+        Some(if focused { b"\x1b[I".to_vec() } else { b"\x1b[O".to_vec() })
+    }
+
+    pub fn set_mouse_position(&mut self, position: PhysicalPosition<f64>, modifiers: ModifiersState) -> Option<Vec<u8>> {
+        let cell = self.cell_at(position);
+        let moved = cell != self.mouse_cell || self.modes.get(Mode::MouseSgrPixel);
+        self.mouse_position = position;
+        self.mouse_cell = cell;
+        if let (Some(start), Some((row, col))) = (self.selection_start, cell) {
+            self.selection = Some(TextSelection::new(start, (self.buffer.display_line(row), col)));
+        }
+        if moved {
+            self.report_mouse(MouseEventKind::Motion, self.mouse_button, modifiers)
+        } else {
+            None
+        }
+    }
+
+    // Clicks are reported to the application when it enabled mouse tracking,
+    // otherwise or with Shift held the left button selects text
+    pub fn mouse_input(&mut self, button: MouseButton, pressed: bool, modifiers: ModifiersState) -> Option<Vec<u8>> {
+        let button = match button {
+            MouseButton::Left => Button::Left,
+            MouseButton::Middle => Button::Middle,
+            MouseButton::Right => Button::Right,
+            MouseButton::Other(_) => return None
+        };
+        // The release of a click that wasn't seen, like one opening a link
+        if !pressed && self.mouse_button != Some(button) {
+            return None;
+        }
+        self.mouse_button = if pressed { Some(button) } else { None };
+        if self.mouse_tracking().is_none() || modifiers.shift() {
+            if button == Button::Left {
+                self.select(pressed);
+            }
+            return None;
+        }
+        let kind = if pressed { MouseEventKind::Press } else { MouseEventKind::Release };
+        self.report_mouse(kind, Some(button), modifiers)
+    }
+
+    // Every line scrolled is reported as a wheel button press
+    pub fn mouse_wheel(&mut self, delta: MouseScrollDelta, modifiers: ModifiersState) -> Option<Vec<u8>> {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y as f64,
+            MouseScrollDelta::PixelDelta(position) => position.y / (self.cell_size.height() * self.scale_factor) as f64
+        };
+        let button = if lines > 0.0 { Button::WheelUp } else { Button::WheelDown };
+        let mut reports = vec![];
+        for _ in 0..lines.abs().ceil() as usize {
+            reports.extend(self.report_mouse(MouseEventKind::Press, Some(button), modifiers)?);
+        }
+        Some(reports).filter(|reports| !reports.is_empty())
+    }
+
+    fn mouse_tracking(&self) -> Option<Mode> {
+        MOUSE_TRACKING_MODES.iter().copied().find(|mode| self.modes.get(*mode))
+    }
+
+    // The report of a mouse event, when the tracking mode asks for this kind
+    // of event. X10 mode only reports presses, without the modifiers.
+    fn report_mouse(&self, kind: MouseEventKind, button: Option<Button>, modifiers: ModifiersState) -> Option<Vec<u8>> {
+        let tracking = self.mouse_tracking()?;
+        let cell = self.mouse_cell?;
+        if modifiers.shift() {
+            return None;
+        }
+        let reported = match kind {
+            MouseEventKind::Press => true,
+            MouseEventKind::Release => tracking != Mode::MouseX10,
+            MouseEventKind::Motion => tracking == Mode::MouseAnyEvent || (tracking == Mode::MouseButtonEvent && button.is_some())
+        };
+        if !reported {
+            return None;
+        }
+        let modifiers = if tracking == Mode::MouseX10 { ModifiersState::empty() } else { modifiers };
+        let pixel = (
+            self.mouse_position.x.max(0.0) as usize,
+            (self.mouse_position.y - (TITLEBAR_MARGIN * self.scale_factor) as f64).max(0.0) as usize
+        );
+        let encoding = MOUSE_ENCODING_MODES.iter().copied().find(|mode| self.modes.get(*mode));
+        MouseEvent { kind, button, modifiers, cell, pixel }.encode(encoding)
+    }
+
+    // The selected text goes to the primary selection when the button is released
+    fn select(&mut self, pressed: bool) {
+        if pressed {
+            self.selection = None;
+            self.selection_start = self.mouse_cell.map(|(row, col)| (self.buffer.display_line(row), col));
+        } else if self.selection_start.take().is_some() {
+            if let Some(selection) = self.selection {
+                let text = self.buffer.text(selection.start, selection.end);
+                self.clipboard.store(Selection::Primary, &text);
+            }
+        }
+    }
+
+    fn hovered_link(&self) -> Option<u32> {
+        let (row, col) = self.mouse_cell?;
+        self.display_cell(row, col).link
+    }
+
+    // The cell shown at a row of the display, selected cells are inverted
+    fn display_cell(&self, row: usize, col: usize) -> Cell {
+        let line = self.buffer.display_line(row);
+        let mut cell = self.buffer.get_line_cell(line, col);
+        if matches!(self.selection, Some(selection) if selection.contains(line, col)) {
+            cell.attrs.inverse = !cell.attrs.inverse;
+        }
+        cell
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.buffer.reset_display();
+    }
+
+    // Scroll the display to put the previous or next prompt at the top
+    pub fn scroll_to_prompt(&mut self, previous: bool) {
+        let top = self.buffer.display_line(0);
+        let mut prompts = self.buffer.marks().iter()
+            .filter(|(_, _, mark)| *mark == Mark::PromptStart)
+            .map(|(line, _, _)| *line);
+        let line = if previous {
+            prompts.rfind(|line| *line < top)
+        } else {
+            prompts.find(|line| *line > top)
+        };
+        match line {
+            Some(line) => self.buffer.scroll_display_to(line),
+            None if !previous => self.buffer.reset_display(),
+            None => {}
+        }
+    }
+
+    // Select the output of the last command and copy it to the clipboard
+    pub fn copy_last_output(&mut self) {
+        let marks = self.buffer.marks();
+        let output = marks.iter().rposition(|(_, _, mark)| *mark == Mark::OutputStart);
+        let (start_line, start_col) = match output {
+            Some(index) => (marks[index].0, marks[index].1),
+            None => return
+        };
+        // The output ends right before the command end mark, or at the cursor
+        // when the command is still running
+        let (end_line, end_col) = marks[output.unwrap()..].iter()
+            .find(|(_, _, mark)| matches!(mark, Mark::CommandEnd(_)))
+            .map(|(line, col, _)| (*line, *col))
+            .unwrap_or((self.buffer.start_line() + self.cursor.row, self.cursor.col));
+        let end = if end_col > 0 {
+            (end_line, end_col - 1)
+        } else if end_line > start_line {
+            (end_line - 1, TERMINAL_COLS as usize - 1)
+        } else {
+            return;
+        };
+        if end < (start_line, start_col) {
+            return;
+        }
+
+        let selection = TextSelection::new((start_line, start_col), end);
+        let text = self.buffer.text(selection.start, selection.end);
+        self.clipboard.store(Selection::Clipboard, &text);
+        self.selection = Some(selection);
+    }
+
+    // The bytes sent to the shell for pasted text, in bracketed paste mode the
+    // application can tell it apart from typed text
+    pub fn paste_data(&self, text: &str) -> Vec<u8> {
+        let text = sanitize_paste(text);
+        if self.modes.get(Mode::BracketedPaste) {
+            format!("{}{}{}", PASTE_START, text, PASTE_END).into_bytes()
+        } else {
+            text.into_bytes()
+        }
+    }
+
+    // The URI of the link under the mouse
+    pub fn hovered_uri(&self) -> Option<String> {
+        self.hyperlinks.get(self.hovered_link()?).map(|link| link.uri.clone())
+    }
+
+    // Where the cursor is shown on the display, when it's visible
+    fn cursor_cell(&self) -> Option<(usize, usize)> {
+        let row = self.cursor.row + self.buffer.display_offset();
+        if self.modes.get(Mode::CursorVisible) && row < TERMINAL_ROWS as usize {
+            Some((row, self.cursor.col.min(TERMINAL_COLS as usize - 1)))
+        } else {
+            None
         }
     }
 
@@ -226,7 +1169,42 @@ impl Terminal {
         });
     }
 
+    // Resolve the foreground and background of a cell, bold text uses the bright
+    // variant of the 8 basic colors
+    fn cell_colors(&self, attrs: &Attributes) -> ([f32; 3], [f32; 3]) {
+        let to_rgb = |color: Color, default: [f32; 3]| match color {
+            Color::Default => default,
+            Color::Indexed(i) => to_float_color(self.palette.color(i)),
+            Color::Rgb(r, g, b) => to_float_color([r, g, b])
+        };
+        let fg = match attrs.fg {
+            Color::Indexed(i) if attrs.bold && i < 8 => Color::Indexed(i + 8),
+            color => color
+        };
+        let (mut default_fg, mut default_bg) = (to_float_color(self.palette.foreground), to_float_color(self.palette.background));
+        if self.modes.get(Mode::ReverseVideo) {
+            std::mem::swap(&mut default_fg, &mut default_bg);
+        }
+        let mut fg = to_rgb(fg, default_fg);
+        let mut bg = to_rgb(attrs.bg, default_bg);
+        if attrs.inverse {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        if attrs.hidden {
+            fg = bg;
+        }
+        (fg, bg)
+    }
+
     pub fn render(&mut self) -> Result<(), SurfaceError> {
+        // Keep showing the last frame while the application updates the screen,
+        // unless it takes so long that it probably never will finish
+        if self.modes.get(Mode::SynchronizedOutput) {
+            if matches!(self.sync_started, Some(started) if started.elapsed() < SYNC_TIMEOUT) {
+                return Ok(());
+            }
+            self.set_mode(Mode::SynchronizedOutput, false);
+        }
         let output = self.surface.get_current_frame()?.output;
         let view = output.texture.create_view(&TextureViewDescriptor::default());
         let mut encoder = self.device.create_command_encoder(&CommandEncoderDescriptor { label: Some("Render encoder") });
@@ -239,47 +1217,33 @@ impl Terminal {
         let mut vertices: Vec<Vertex> = vec![];
         let mut indices: Vec<u16> = vec![];
 
-        for row in 0..TERMINAL_ROWS {
-            for col in 0..TERMINAL_COLS {
-                let i = (row * TERMINAL_COLS + col) as f32;
-                let [x, y] = [col as f32 * self.cell_size.width(), row as f32 * self.cell_size.height() + TITLEBAR_MARGIN];
-                let [width, height] = [self.cell_size.width(), self.cell_size.height()];
-                let v_top_left = Vertex {
-                    position: [
-                        (x - origin_width) / origin_width,
-                        (origin_height - y) / origin_height,
-                        0.0
-                    ],
-                    color: CELL_COLOR
-                };
-                let v_top_right = Vertex {
-                    position: [
-                        (x + width - origin_width) / origin_width,
-                        (origin_height - y) / origin_height,
-                        0.0
-                    ],
-                    color: CELL_COLOR
-                };
-                let v_bottom_right = Vertex {
-                    position: [
-                        (x + width - origin_width) / origin_width,
-                        (origin_height - (y + height)) / origin_height,
-                        0.0
-                    ],
-                    color: CELL_COLOR
-                };
-                let v_bottom_left = Vertex {
-                    position: [
-                        (x - origin_width) / origin_width,
-                        (origin_height - (y + height)) / origin_height,
-                        0.0
-                    ],
-                    color: CELL_COLOR
-                };
+        let [width, height] = [self.cell_size.width(), self.cell_size.height()];
+        let hovered_link = self.hovered_link();
+        for row in 0..TERMINAL_ROWS as usize {
+            for col in 0..TERMINAL_COLS as usize {
+                let cell = self.display_cell(row, col);
+                let (fg, bg) = self.cell_colors(&cell.attrs);
+                let [x, y] = [col as f32 * width, row as f32 * height + TITLEBAR_MARGIN];
+                push_quad(&mut vertices, &mut indices, [x, y, width, height], [origin_width, origin_height], bg);
+
+                let line = (height / 16.0).max(1.0);
+                if cell.attrs.underline || (cell.link.is_some() && cell.link == hovered_link) {
+                    push_quad(&mut vertices, &mut indices, [x, y + height - line * 2.0, width, line], [origin_width, origin_height], fg);
+                }
+                if cell.attrs.strikethrough {
+                    push_quad(&mut vertices, &mut indices, [x, y + height / 2.0, width, line], [origin_width, origin_height], fg);
+                }
+            }
+        }
 
-                vertices.append(&mut vec![ v_top_left, v_top_right, v_bottom_right, v_bottom_left ]);
-                let idx = i as u16 * 4;
-                indices.append(&mut vec![ idx + 0, idx + 2, idx + 1, idx + 2, idx + 0, idx + 3 ]);
+        // The cursor of an unfocused window is only an outline
+        let cursor_cell = self.cursor_cell();
+        if let (Some((row, col)), false) = (cursor_cell, self.focused) {
+            let [x, y] = [col as f32 * width, row as f32 * height + TITLEBAR_MARGIN];
+            let line = (height / 16.0).max(1.0);
+            let color = to_float_color(self.palette.cursor);
+            for rect in [[x, y, width, line], [x, y + height - line, width, line], [x, y, line, height], [x + width - line, y, line, height]] {
+                push_quad(&mut vertices, &mut indices, rect, [origin_width, origin_height], color);
             }
         }
 
@@ -295,6 +1259,8 @@ impl Terminal {
             usage: BufferUsages::INDEX
         });
 
+        // The titlebar margin isn't covered by the cells
+        let background = to_float_color(self.palette.background);
         {
             let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
                 label: Some("Render pass"),
@@ -302,10 +1268,10 @@ impl Terminal {
                     view: &view,
                     resolve_target: None,
                     ops: Operations {
-                        load: LoadOp::Clear(Color {
-                            r: 0.01,
-                            g: 0.01,
-                            b: 0.01,
+                        load: LoadOp::Clear(wgpu::Color {
+                            r: background[0] as f64,
+                            g: background[1] as f64,
+                            b: background[2] as f64,
                             a: 1.0
                         }),
                         store: true
@@ -322,12 +1288,21 @@ impl Terminal {
 
         for row in 0..TERMINAL_ROWS as usize {
             for col in 0..TERMINAL_COLS as usize {
-                let c = self.buffer.get_char_at(row + self.start_line, col) as char;
-                self.put_char(&c.to_string(), CHR_COLOR, row as f32, col as f32);
+                let cell = self.display_cell(row, col);
+                if cell.width == 0 {
+                    continue;
+                }
+                let (fg, _) = self.cell_colors(&cell.attrs);
+                let alpha = if cell.attrs.faint { 0.5 } else { 1.0 };
+                let grapheme = self.buffer.grapheme(&cell);
+                self.put_char(&grapheme, [fg[0], fg[1], fg[2], alpha], row as f32, col as f32);
             }
         }
 
-        self.put_char(CUR_CHAR, CUR_COLOR, (self.cursor.row - self.start_line) as f32, self.cursor.col as f32);
+        if let (Some((row, col)), true) = (cursor_cell, self.focused) {
+            let [r, g, b] = to_float_color(self.palette.cursor);
+            self.put_char(CUR_CHAR, [r, g, b, CUR_ALPHA], row as f32, col as f32);
+        }
 
         self.glyph_brush.draw_queued(&self.device, &mut self.staging_belt, &mut encoder, &view, self.size.width, self.size.height).ok();
         self.staging_belt.finish();
@@ -338,3 +1313,105 @@ impl Terminal {
     }
 }
 
+// Push a rectangle of [x, y, width, height] in logical pixels as two triangles,
+// converting it into the clip space around the origin
+fn push_quad(vertices: &mut Vec<Vertex>, indices: &mut Vec<u16>, rect: [f32; 4], origin: [f32; 2], color: [f32; 3]) {
+    let [x, y, width, height] = rect;
+    let [origin_width, origin_height] = origin;
+    let idx = vertices.len() as u16;
+    for [px, py] in [[x, y], [x + width, y], [x + width, y + height], [x, y + height]] {
+        vertices.push(Vertex {
+            position: [
+                (px - origin_width) / origin_width,
+                (origin_height - py) / origin_height,
+                0.0
+            ],
+            color
+        });
+    }
+    indices.extend_from_slice(&[ idx, idx + 2, idx + 1, idx + 2, idx, idx + 3 ]);
+}
+
+fn to_float_color(color: [u8; 3]) -> [f32; 3] {
+    [color[0] as f32 / 255.0, color[1] as f32 / 255.0, color[2] as f32 / 255.0]
+}
+
+// Newlines are sent as carriage returns like the Enter key. Other control
+// characters are dropped, so that the pasted text can't end the bracket and
+// have the rest run as commands
+fn sanitize_paste(text: &str) -> String {
+    text.replace(PASTE_END, "")
+        .replace("\r\n", "\r")
+        .chars()
+        .map(|c| if c == '\n' { '\r' } else { c })
+        .filter(|&c| c == '\r' || c == '\t' || !c.is_control())
+        .collect()
+}
+
+// Host names are compared in full, only a name without a domain matches the
+// first label of the other one, so `foo` is `foo.local` but `foo.a.com` isn't
+// `foo.b.com`
+fn same_host(a: &str, b: &str) -> bool {
+    let (a, b) = (a.to_lowercase(), b.to_lowercase());
+    if a == b {
+        return true;
+    }
+    let short_name = |name: &str| name.split('.').next().unwrap_or_default().to_string();
+    (!a.contains('.') || !b.contains('.')) && short_name(&a) == short_name(&b)
+}
+
+fn local_hostname() -> Option<String> {
+    let output = Command::new("hostname").output().ok()?;
+    let hostname = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    if hostname.is_empty() { None } else { Some(hostname) }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            },
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_paste_strips_escapes() {
+        let text = sanitize_paste("a\x1b[201~b\x1b]52;c;?\x07\r\nc");
+        assert!(!text.contains('\x1b'));
+        assert!(!text.contains('\x07'));
+        assert!(!text.contains(PASTE_END));
+        assert!(!text.contains('\n'));
+        assert_eq!(text, "ab]52;c;?\rc");
+    }
+
+    #[test]
+    fn host_names() {
+        assert!(same_host("devbox", "devbox"));
+        assert!(same_host("DevBox.local", "devbox.local"));
+        assert!(same_host("devbox", "devbox.local"));
+        assert!(same_host("devbox.local", "devbox"));
+        assert!(!same_host("devbox.corp.example", "devbox.cloud.example"));
+        assert!(!same_host("devbox", "otherbox"));
+    }
+
+    #[test]
+    fn sanitize_paste_newlines() {
+        assert_eq!(sanitize_paste("a\nb\r\nc\rd\te"), "a\rb\rc\rd\te");
+    }
+}